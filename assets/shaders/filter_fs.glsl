@@ -2,55 +2,26 @@
 in vec2 position_pass;
 out vec4 final_color;
 
-uniform sampler3D world_data_texture;
 uniform sampler2D current_lighting_texture;
 uniform sampler2D current_position_texture;
+uniform sampler2D current_normal_texture;
+uniform sampler2D current_material_texture;
 uniform float time;
 uniform float VOXEL_SIZE_XY;
 uniform float VOXEL_SIZE_Z;
 
+// 0: shaded, 1: intensity only, 2: lighting only, 3: normals
+uniform int render_mode;
+// Center and width of the displayed intensity window
+uniform vec2 window_level;
+// 0: grayscale, 1: hot
+uniform int colormap;
+uniform bool bilateral_filter;
+uniform float kernel_size;
+uniform float sigma_space;
+uniform float sigma_range;
+uniform float light_diminution;
 
-const float WORLD_SIZE = 256;
-const vec2 window_size = vec2(1024.0, 768.0);
-const vec2 delta = vec2(1.0 / window_size.x, 1.0 / window_size.y);
-const float LIGHT_DIMINUTION = 15.0;
-
-
-vec4 get_texture_color(vec3 ray_position){
-    vec3 position_rectification = vec3(ray_position.x, ray_position.y, ray_position.z * (VOXEL_SIZE_XY/VOXEL_SIZE_Z));
-    vec3 position_in_texture = floor(position_rectification/VOXEL_SIZE_XY)/WORLD_SIZE;
-    return texture(world_data_texture, position_in_texture);
-}
-
-
-bool is_out_of_map(vec3 position){
-    return position.x < 0.0 || position.y < 0.0 || position.z < 0.0 || position.x > VOXEL_SIZE_XY * WORLD_SIZE || position.y > VOXEL_SIZE_XY * WORLD_SIZE || position.z > VOXEL_SIZE_Z * WORLD_SIZE;
-}
-
-vec3 get_normal(vec3 position){
-    vec3 position_rectification = vec3(position.x, position.y, position.z * (VOXEL_SIZE_XY/VOXEL_SIZE_Z));
-    vec3 center = (floor(position_rectification/VOXEL_SIZE_XY) + vec3(0.5, 0.5, 0.5)) * VOXEL_SIZE_XY;
-    if(abs(center.x - position.x) > abs(center.y - position.y) && abs(center.x - position.x) > abs(center.z - position.z)){
-        if(center.x - position.x > 0.0){
-            return vec3(-1.0, 0.0, 0.0);
-        }
-        return vec3(1.0, 0.0, 0.0);
-    }
-    if(abs(center.y - position.y) > abs(center.z - position.z)){
-        if(center.y - position.y > 0.0){
-            return vec3(0.0, -1.0, 0.0);
-        }
-        return vec3(0.0, 1.0, 0.0);
-    }
-    if(center.z - position.z > 0.0){
-        return vec3(0.0, 0.0, -1.0);
-    }
-    return vec3(0.0, 0.0, 1.0);
-}
-
-const float KERNEL_SIZE = 5.0; // 5
-const float SIGMA_SPACE = 0.1; // 0.5
-const float SIGMA_RANGE = 0.2; // 20
 const float M_PI = 3.1415;
 
 float gaussian(float x, float sigma) {
@@ -61,33 +32,33 @@ vec3 apply_bilateral_filter(vec2 uv) {
     
     vec2 texelSize = 2.0 / vec2(textureSize(current_lighting_texture, 0));
 
-    vec3 centralColor = texture2D(current_lighting_texture, uv).rgb;
+    vec3 centralColor = texture(current_lighting_texture, uv).rgb;
     //centralColor = vec3(pow(min(centralColor.x, 0.9), 0.2), pow(min(centralColor.y, 0.9), 0.2), pow(min(centralColor.z, 0.9), 0.2));
-    vec3 centralPosition = texture2D(current_position_texture, uv).rgb;
-    vec3 centralNormal = get_normal(centralPosition);
+    vec3 centralPosition = texture(current_position_texture, uv).rgb;
+    vec3 centralNormal = texture(current_normal_texture, uv).xyz;
 
     vec3 result = vec3(0.0);
     float totalWeight = 0.0;
 
-    for (float x = -KERNEL_SIZE; x <= KERNEL_SIZE; x++) {
-        for (float y = -KERNEL_SIZE; y <= KERNEL_SIZE; y++) {
+    for (float x = -kernel_size; x <= kernel_size; x++) {
+        for (float y = -kernel_size; y <= kernel_size; y++) {
             vec2 offset = vec2(x, y) * texelSize;
             vec2 sampleUV = uv + offset;
 
-            vec3 sampleColor = texture2D(current_lighting_texture, sampleUV).rgb;
+            vec3 sampleColor = texture(current_lighting_texture, sampleUV).rgb;
             //sampleColor = vec3(pow(min(sampleColor.x, 0.9), 0.2), pow(min(sampleColor.y, 0.9), 0.2), pow(min(sampleColor.z, 0.9), 0.2));
-            vec3 samplePosition = texture2D(current_position_texture, sampleUV).rgb;
-            vec3 sampleNormal = get_normal(samplePosition);
+            vec3 samplePosition = texture(current_position_texture, sampleUV).rgb;
+            vec3 sampleNormal = texture(current_normal_texture, sampleUV).xyz;
 
             float spaceDistance = length(offset);
             float rangeDistance = length(centralColor - sampleColor);
             float positionDistance = length(centralPosition - samplePosition);
             float normalDistance = length(centralNormal - sampleNormal);
 
-            float spaceWeight = gaussian(spaceDistance, SIGMA_SPACE);
-            float rangeWeight = gaussian(rangeDistance, SIGMA_RANGE);
-            float positionWeight = gaussian(positionDistance, SIGMA_SPACE);
-            float normalWeight = gaussian(normalDistance, SIGMA_SPACE);
+            float spaceWeight = gaussian(spaceDistance, sigma_space);
+            float rangeWeight = gaussian(rangeDistance, sigma_range);
+            float positionWeight = gaussian(positionDistance, sigma_space);
+            float normalWeight = gaussian(normalDistance, sigma_space);
 
             float weight = spaceWeight * rangeWeight * positionWeight * normalWeight;
 
@@ -100,8 +71,50 @@ vec3 apply_bilateral_filter(vec2 uv) {
 }
 
 
+// Raymarching and lighting passes are rendered at the render scale: the position is read from
+// the nearest texel, the lighting is interpolated between the neighbouring texels which see the
+// same surface so the edges of the voxels stay sharp
+ivec2 get_nearest_texel(vec2 uv){
+    ivec2 render_size = textureSize(current_position_texture, 0);
+    return clamp(ivec2(uv * vec2(render_size)), ivec2(0), render_size - 1);
+}
+
+vec3 get_upsampled_lighting(vec2 uv, vec3 position){
+    ivec2 render_size = textureSize(current_lighting_texture, 0);
+    vec2 texel = uv * vec2(render_size) - 0.5;
+    vec2 fraction = fract(texel);
+    vec3 result = vec3(0.0);
+    float total_weight = 0.0;
+    for (int i = 0; i < 4; i++){
+        ivec2 offset = ivec2(i % 2, i / 2);
+        ivec2 sample_texel = clamp(ivec2(floor(texel)) + offset, ivec2(0), render_size - 1);
+        vec4 sample_position = texelFetch(current_position_texture, sample_texel, 0);
+        vec2 bilinear = mix(1.0 - fraction, fraction, vec2(offset));
+        float weight = bilinear.x * bilinear.y;
+        if (sample_position.a < 0.5 || length(sample_position.xyz - position) > 2.0 * VOXEL_SIZE_XY){
+            weight *= 0.001;
+        }
+        result += texelFetch(current_lighting_texture, sample_texel, 0).rgb * weight;
+        total_weight += weight;
+    }
+    return result / max(total_weight, 1e-6);
+}
+
+
+vec3 apply_window(vec3 intensity){
+    return clamp((intensity - (window_level.x - 0.5 * window_level.y)) / window_level.y, 0.0, 1.0);
+}
+
+vec3 apply_colormap(vec3 color){
+    if (colormap == 1){
+        float value = dot(color, vec3(1.0 / 3.0));
+        return clamp(vec3(3.0 * value, 3.0 * value - 1.0, 3.0 * value - 2.0), 0.0, 1.0);
+    }
+    return color;
+}
+
 float smooth_float(float value){
-    float value_1 = min(1.0, 1.0 * (2.0/(1.0 + exp(-LIGHT_DIMINUTION * value)) - 1.0));
+    float value_1 = min(1.0, 1.0 * (2.0/(1.0 + exp(-light_diminution * value)) - 1.0));
     return 20 * value_1;
 }
 
@@ -112,15 +125,24 @@ void main()
 
     vec2 uv = 0.5 * (position_pass + vec2(1.0));
 
-    vec4 current_position_texture_value = texture(current_position_texture, uv);
+    ivec2 nearest_texel = get_nearest_texel(uv);
+    vec4 current_position_texture_value = texelFetch(current_position_texture, nearest_texel, 0);
     if (current_position_texture_value.a > 0.5){
-        vec3 filtered_light = texture(current_lighting_texture, uv).rgb;
-        float pow_factor = 0.6;
+        vec3 filtered_light = bilateral_filter ? apply_bilateral_filter(uv) : get_upsampled_lighting(uv, current_position_texture_value.xyz);
         filtered_light = vec3(smooth_float(filtered_light.x), smooth_float(filtered_light.y), smooth_float(filtered_light.z));
 
-        
-        vec4 texture_color = get_texture_color(current_position_texture_value.xyz);
-        final_color = vec4(filtered_light * texture_color.xyz, texture_color.a);
+        // Intensity and label of the hit voxel are written by the raymarching pass
+        vec4 material = texelFetch(current_material_texture, nearest_texel, 0);
+        vec3 color = apply_colormap(apply_window(material.rgb));
+        vec3 result = filtered_light * color;
+        if (render_mode == 1){
+            result = color;
+        } else if (render_mode == 2){
+            result = filtered_light / 20.0;
+        } else if (render_mode == 3){
+            result = 0.5 * texelFetch(current_normal_texture, nearest_texel, 0).xyz + 0.5;
+        }
+        final_color = vec4(result, material.a / 255.0);
     }
 }
 
@@ -0,0 +1,78 @@
+// Volume sampling shared by the passes tracing rays in the volume.
+// BRICK_SIZE is defined by the application.
+
+uniform sampler3D world_data_texture;
+uniform float VOXEL_SIZE_XY;
+uniform float VOXEL_SIZE_Z;
+uniform float BRICK_COUNT;
+// Voxels along each side of the cubic grid the textures are sampled in
+uniform float GRID_SIZE;
+uniform bool use_paging;
+uniform sampler3D brick_atlas_texture;
+uniform sampler3D indirection_texture;
+uniform float ATLAS_BRICK_COUNT;
+uniform float PREVIEW_LOD_LEVEL;
+// Voxels outside the clip box (in texture coordinates) or the intensity range are transparent
+uniform vec3 clip_minimum;
+uniform vec3 clip_maximum;
+uniform vec2 visible_range;
+
+
+vec3 get_voxel_size(){
+    return vec3(VOXEL_SIZE_XY, VOXEL_SIZE_XY, VOXEL_SIZE_Z);
+}
+
+// Sample the volume at normalized texture coordinates and level of detail. A streamed volume
+// is looked up through the indirection texture, bricks which are not resident yet are read
+// from the low resolution preview.
+vec4 sample_volume(vec3 position_in_texture, float level){
+    if (!use_paging || level >= PREVIEW_LOD_LEVEL){
+        return textureLod(world_data_texture, position_in_texture, max(level, PREVIEW_LOD_LEVEL) - PREVIEW_LOD_LEVEL);
+    }
+    vec4 page = texture(indirection_texture, position_in_texture);
+    if (page.a < 0.75){
+        return textureLod(world_data_texture, position_in_texture, 0.0);
+    }
+    vec3 slot = floor(page.rgb * 255.0 + 0.5);
+    vec3 voxel_in_brick = floor(fract(position_in_texture * BRICK_COUNT) * BRICK_SIZE) + 0.5;
+    return texture(brick_atlas_texture, (slot * BRICK_SIZE + voxel_in_brick) / (ATLAS_BRICK_COUNT * BRICK_SIZE));
+}
+
+bool is_out_of_map(vec3 position){
+    return position.x < 0.0 || position.y < 0.0 || position.z < 0.0 || position.x > VOXEL_SIZE_XY * GRID_SIZE || position.y > VOXEL_SIZE_XY * GRID_SIZE || position.z > VOXEL_SIZE_Z * GRID_SIZE;
+}
+
+bool is_cube(vec3 position, float level){
+    float cell_size = exp2(level);
+    vec3 position_in_texture = (floor(position / get_voxel_size() / cell_size) + 0.5) * cell_size / GRID_SIZE;
+    if (position_in_texture.x < 0 || position_in_texture.y < 0 || position_in_texture.z < 0 || position_in_texture.x > 1 || position_in_texture.y > 1 || position_in_texture.z > 1){
+        return false;
+    }
+    if (any(lessThan(position_in_texture, clip_minimum)) || any(greaterThan(position_in_texture, clip_maximum))){
+        return false;
+    }
+    vec4 voxel = sample_volume(position_in_texture, level);
+    return voxel.a > 0.0 && voxel.r >= visible_range.x && voxel.r <= visible_range.y;
+}
+
+// Distance to the border of the current voxel of the given level of detail
+float distance_to_border(vec3 position, vec3 direction, float level){
+    vec3 voxel_size = get_voxel_size() * exp2(level);
+    vec3 fraction = fract(position / voxel_size);
+    vec3 minimum = max((1.0001 - fraction) / direction, (-0.0001 - fraction) / direction) * voxel_size;
+    return min(minimum.x, min(minimum.y, minimum.z));
+}
+
+// Normal of the face of the voxel of the given level of detail the position lies on
+vec3 get_normal(vec3 position, float level){
+    vec3 voxel_size = get_voxel_size() * exp2(level);
+    vec3 offset_from_center = fract(position / voxel_size) - 0.5;
+    vec3 distance_from_center = abs(offset_from_center);
+    if (distance_from_center.x > distance_from_center.y && distance_from_center.x > distance_from_center.z){
+        return vec3(sign(offset_from_center.x), 0.0, 0.0);
+    }
+    if (distance_from_center.y > distance_from_center.z){
+        return vec3(0.0, sign(offset_from_center.y), 0.0);
+    }
+    return vec3(0.0, 0.0, sign(offset_from_center.z));
+}
@@ -0,0 +1,11 @@
+#version 400
+in vec2 texture_coords_pass;
+in vec4 color_pass;
+out vec4 final_color;
+
+// Coverage of the glyphs in the red channel
+uniform sampler2D font_texture;
+
+void main() {
+    final_color = vec4(color_pass.rgb, color_pass.a * texture(font_texture, texture_coords_pass).r);
+}
@@ -0,0 +1,12 @@
+#version 330
+in vec2 position;
+in vec2 texture_coords;
+in vec4 color;
+out vec2 texture_coords_pass;
+out vec4 color_pass;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    texture_coords_pass = texture_coords;
+    color_pass = color;
+}
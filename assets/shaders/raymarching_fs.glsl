@@ -1,39 +1,98 @@
 #version 400
 in vec2 position_pass;
-out vec4 final_color;
 
-uniform sampler3D world_data_texture;
+// G-buffer: hit position and 1 + level of detail (0.25 if nothing is hit), face normal,
+// coordinates of the hit voxel and its level, intensity of the voxel and label ID
+layout(location = 0) out vec4 out_position;
+layout(location = 1) out vec4 out_normal;
+layout(location = 2) out vec4 out_voxel;
+layout(location = 3) out vec4 out_material;
+
+// Intensity range of the visible voxels of each brick and macro cell (MACRO_CELL_SIZE bricks
+// along each side), the minimum is above the maximum when there is none
+uniform sampler3D occupancy_texture;
+uniform sampler3D macro_occupancy_texture;
 
 uniform mat4 invert_mvp;
+uniform mat4 invert_mvp_ortho;
+uniform mat4 mvp;
 uniform vec3 camera_position;
-uniform float VOXEL_SIZE_XY;
-uniform float VOXEL_SIZE_Z;
+uniform vec3 camera_forward;
+// Parallel rays starting across the view plane instead of rays from the camera position
+uniform bool orthographic;
+// Length of the rays, they stop once past the farthest corner of the volume
+uniform float max_ray_distance;
+uniform float LOD_DISTANCE;
+uniform float MAX_LOD_LEVEL;
+
+#include "include/volume.glsl"
+
+// Level of detail used at this position: the further from the camera, the coarser the voxels
+float get_lod_level(vec3 position){
+    float level = floor(log2(max(length(position - camera_position) / LOD_DISTANCE, 1.0)));
+    return min(level, MAX_LOD_LEVEL);
+}
+
+vec3 get_brick_size(){
+    return get_voxel_size() * GRID_SIZE / BRICK_COUNT;
+}
 
-const float WORLD_SIZE = 256;
+ivec3 get_brick(vec3 position){
+    return min(ivec3(floor(position / get_brick_size())), ivec3(BRICK_COUNT) - 1);
+}
 
+// No voxel of the range is shown: none is visible or none is in the visible intensities
+bool is_range_hidden(vec2 intensity_range){
+    return intensity_range.y < intensity_range.x || intensity_range.y < visible_range.x || intensity_range.x > visible_range.y;
+}
 
-bool is_cube(vec3 position){
+// A brick is a block of voxels of the world texture: if it is empty, the ray can jump to its border
+bool is_brick_empty(vec3 position){
     vec3 position_rectification = vec3(position.x / VOXEL_SIZE_XY, position.y / VOXEL_SIZE_XY, position.z / VOXEL_SIZE_Z);
-    vec3 position_in_texture = floor(position_rectification)/WORLD_SIZE;
-    if (position_in_texture.x < 0 || position_in_texture.y < 0 || position_in_texture.z < 0 || position_in_texture.x > 1 || position_in_texture.y > 1 || position_in_texture.z > 1){
-        return false;
+    if (use_paging){
+        // Only bricks known to be empty are skipped, the others may be shown by the preview
+        float page_flag = texture(indirection_texture, position_rectification / GRID_SIZE).a;
+        return page_flag > 0.25 && page_flag < 0.75;
     }
-    if (texture(world_data_texture, position_in_texture).a > 0.0){
-        return true;
+    return is_range_hidden(texelFetch(occupancy_texture, get_brick(position), 0).rg);
+}
+
+// Streamed volumes have no macro cells, their bricks are tested one by one
+bool is_macro_cell_empty(vec3 position){
+    if (use_paging){
+        return false;
     }
-    return false;
+    return is_range_hidden(texelFetch(macro_occupancy_texture, get_brick(position) / MACRO_CELL_SIZE, 0).rg);
 }
 
-float distance_to_border(vec3 position , vec3 direction){
-    float minimum_x = max((1.0001 - fract(position.x/VOXEL_SIZE_XY)) / direction.x, (-0.0001 - fract(position.x/VOXEL_SIZE_XY)) / direction.x);
-    float minimum_y = max((1.0001 - fract(position.y/VOXEL_SIZE_XY)) / direction.y, (-0.0001 - fract(position.y/VOXEL_SIZE_XY)) / direction.y);
-    float minimum_z = max((1.0001 - fract(position.z/VOXEL_SIZE_Z)) / direction.z, (-0.0001 - fract(position.z/VOXEL_SIZE_Z)) / direction.z);
+float distance_to_cell_border(vec3 position, vec3 direction, vec3 cell_size){
+    vec3 fraction = fract(position / cell_size);
+    vec3 minimum = max((1.0001 - fraction) / direction, (-0.0001 - fraction) / direction) * cell_size;
+    return min(minimum.x, min(minimum.y, minimum.z));
+}
 
-    return min(VOXEL_SIZE_XY * minimum_x, min(VOXEL_SIZE_XY * minimum_y, VOXEL_SIZE_Z * minimum_z));
+void write_miss(vec3 position){
+    out_position = vec4(position, 0.25);
+    out_normal = vec4(0.0);
+    out_voxel = vec4(-1.0);
+    out_material = vec4(0.0);
+    gl_FragDepth = 1.0;
 }
 
-bool is_out_of_map(vec3 position){
-    return position.x < 0.0 || position.y < 0.0 || position.z < 0.0 || position.x > VOXEL_SIZE_XY * WORLD_SIZE || position.y > VOXEL_SIZE_XY * WORLD_SIZE || position.z > VOXEL_SIZE_Z * WORLD_SIZE;
+void write_hit(vec3 position, float level){
+    vec3 position_rectification = vec3(position.x / VOXEL_SIZE_XY, position.y / VOXEL_SIZE_XY, position.z / VOXEL_SIZE_Z);
+    float cell_size = exp2(level);
+    vec3 voxel = floor(position_rectification / cell_size) * cell_size;
+    vec4 color = sample_volume((voxel + 0.5 * cell_size) / GRID_SIZE, level);
+
+    // The level of detail is stored with the hit flag so next passes sample the same voxel
+    out_position = vec4(position, 1.0 + level);
+    out_normal = vec4(get_normal(position, level), 0.0);
+    out_voxel = vec4(voxel, level);
+    out_material = vec4(color.rgb, floor(color.a * 255.0 + 0.5));
+
+    vec4 clip_position = mvp * vec4(position, 1.0);
+    gl_FragDepth = clamp(0.5 * clip_position.z / clip_position.w + 0.5, 0.0, 1.0);
 }
 
 // float distance_to_grid(vec3 position, float grid_space, float grid_radius){
@@ -55,20 +114,38 @@ void main()
     // Find origin position and direction of ray
     // Direction is computed using MVP matrix
     vec3 ray_forward = normalize(vec4(invert_mvp * vec4(position_pass, 0.001, 1.0)).xyz);
-    vec3 ray_position = camera_position + 0.0 * ray_forward;
+    vec3 ray_origin = camera_position;
+    if (orthographic) {
+        // Point of the view plane in front of this pixel, relative to the camera
+        vec4 plane_position = invert_mvp_ortho * vec4(position_pass, -1.0, 1.0);
+        ray_origin += plane_position.xyz / plane_position.w;
+        ray_forward = camera_forward;
+    }
+    vec3 ray_position = ray_origin;
 
-    while(length(ray_position - camera_position) < 5.0){
+    while(length(ray_position - ray_origin) < max_ray_distance){
         if (!is_out_of_map(ray_position)) {
             ray_entered_world = true;
         }
 
         if (is_out_of_map(ray_position) && ray_entered_world){
-            final_color = vec4(ray_position, 0.25);
+            write_miss(ray_position);
             return;
         }
 
-        if (!is_out_of_map(ray_position) && is_cube(ray_position)){
-            final_color = vec4(ray_position, 1.0);
+        // Skip the whole macro cell or brick when it contains no visible voxel
+        if (!is_out_of_map(ray_position) && is_macro_cell_empty(ray_position)){
+            ray_position += distance_to_cell_border(ray_position, ray_forward, get_brick_size() * MACRO_CELL_SIZE) * ray_forward;
+            continue;
+        }
+        if (!is_out_of_map(ray_position) && is_brick_empty(ray_position)){
+            ray_position += distance_to_cell_border(ray_position, ray_forward, get_brick_size()) * ray_forward;
+            continue;
+        }
+
+        float level = get_lod_level(ray_position);
+        if (!is_out_of_map(ray_position) && is_cube(ray_position, level)){
+            write_hit(ray_position, level);
             return;
         }
         
@@ -79,10 +156,10 @@ void main()
         //     return;
         // }
        
-        ray_position += distance_to_border(ray_position, ray_forward) * ray_forward;
+        ray_position += distance_to_border(ray_position, ray_forward, level) * ray_forward;
         
       
     }
 
-    final_color = vec4(ray_position, 0.25);
+    write_miss(ray_position);
 }
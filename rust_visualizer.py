@@ -24,12 +24,14 @@ class RustVisualizer:
         # Ouvrir le fichier en mode binaire pour l'écriture
         with open(f"{path_to_rust_prog}/saves/save_{file_name}.bin", "wb") as file:
             print(pixel_size)
+            # Tag of the header with the dimensions on 32 bits
+            file.write(b"BOLV")
             data = struct.pack(
                 'BBB', pixel_size[0], pixel_size[1], pixel_size[2])
             file.write(data)
 
             data = struct.pack(
-                'BBB', self.array.shape[0], self.array.shape[1], self.array.shape[2])
+                '<III', self.array.shape[0], self.array.shape[1], self.array.shape[2])
             file.write(data)
             for x in range(self.array.shape[0]):
                 for y in range(self.array.shape[1]):
@@ -44,7 +46,7 @@ class RustVisualizer:
 
     def start_visualizer(self, pixel_size, path_to_rust_prog):
         filename = self.export_to_bin(pixel_size, path_to_rust_prog)
-        command_to_execute = f"cd {path_to_rust_prog} && cargo run --release -- {filename}"
+        command_to_execute = f"cargo run --release --manifest-path {path_to_rust_prog}/Cargo.toml -- {path_to_rust_prog}/saves/{filename}.bin"
         print("command:", command_to_execute)
         subprocess.run(command_to_execute, shell=True,
                        stdout=subprocess.PIPE, stderr=subprocess.PIPE, text=True)
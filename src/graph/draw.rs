@@ -1,394 +1,472 @@
-use std::ffi::CString;
+use cgmath::{InnerSpace, Matrix4, Vector2, Vector3};
+use glutin::{window::Window, ContextWrapper, PossiblyCurrent};
 
-use cgmath::Vector2;
-use glutin::{event::VirtualKeyCode, window::Window, ContextWrapper, PossiblyCurrent};
-
-use crate::{io::input_player::InputManager, player::player::Player, world::world_data::WorldData};
+use crate::{
+    io::{bindings::Action, input_player::InputManager},
+    player::{camera::ProjectionSettings, player::Player},
+    world::world_data::WorldData,
+};
 
 use super::{
-    mesh::Mesh,
-    texture::{Texture2D, TextureParameter},
+    gui::Gui,
+    hud::{CursorSample, Hud, HudInfo},
+    overlay::Overlay,
+    pixel_readback::PixelReadback,
+    render_graph::{RenderGraph, UniformValue},
+    render_scale::RenderScale,
+    render_settings::RenderSettings,
 };
 
+const RENDER_GRAPH_PATH: &str = "render_graph.json";
+const SETTINGS_PANEL_WIDTH: f32 = 340.0;
+// Targets of the G-buffer read under the cursor
+const CURSOR_TARGETS: [&str; 3] = ["position", "voxel", "material"];
+
 pub struct Drawer {
-    raymarching_quad: Option<Mesh>,
-    previous_position_texture: Option<Texture2D>,
-    previous_lighting_texture: Option<Texture2D>,
-    lighting_quad: Option<Mesh>,
-    filter_quad: Option<Mesh>,
+    render_graph: Option<RenderGraph>,
+    render_scale: RenderScale,
+    window_size: (i32, i32),
+    overlay: Option<Overlay>,
+    // Pixels of the targets shown in the HUD and the statistics
+    pixel_readback: Option<PixelReadback>,
+    hud: Hud,
+    gui: Gui,
+    gui_visible: bool,
+    render_settings: RenderSettings,
+    // Settings restored by the reset button of the panel
+    default_render_settings: RenderSettings,
+    // Set when a setting changed, the lighting accumulated with the old settings is discarded
+    reset_accumulation: bool,
+    // Projection of the previous frame, the reprojected lighting is wrong when it changes
+    orthographic: bool,
 }
 
 impl Drawer {
-    pub fn new() -> Self {
+    pub fn new(camera_projection: ProjectionSettings) -> Self {
         return Drawer {
-            raymarching_quad: Option::None,
-            previous_position_texture: Option::None,
-            previous_lighting_texture: Option::None,
-            lighting_quad: Option::None,
-            filter_quad: Option::None,
+            render_graph: Option::None,
+            render_scale: RenderScale::new(),
+            window_size: (0, 0),
+            overlay: Option::None,
+            pixel_readback: Option::None,
+            hud: Hud::new(),
+            gui: Gui::new(),
+            gui_visible: false,
+            render_settings: RenderSettings::new(camera_projection),
+            default_render_settings: RenderSettings::new(camera_projection),
+            reset_accumulation: false,
+            orthographic: false,
         };
     }
 
     pub unsafe fn load_content(&mut self, gl_context: &ContextWrapper<PossiblyCurrent, Window>) {
-        let screen_size = (
+        self.window_size = (
             gl_context.window().inner_size().width as i32,
             gl_context.window().inner_size().height as i32,
         );
+        // Passes with a target run at the render resolution, the last pass upsamples
+        // them to the window
+        let render_graph =
+            RenderGraph::load(RENDER_GRAPH_PATH, self.render_size(), self.window_size)
+                .unwrap_or_else(|error| panic!("{}", error));
+        self.render_graph = Some(render_graph);
+        let overlay = Overlay::new().unwrap_or_else(|error| panic!("{}", error));
+        self.overlay = Some(overlay);
+        self.pixel_readback = Some(PixelReadback::new());
+    }
 
-        self.raymarching_quad = Some(Mesh::new(
-            "raymarching".to_string(),
-            true,
-            Some(TextureParameter::new_float_parameter(screen_size)),
-        ));
-
-        self.lighting_quad = Some(Mesh::new(
-            "lighting".to_string(),
-            true,
-            Some(TextureParameter::new_float_parameter(screen_size)),
-        ));
-
-        self.filter_quad = Some(Mesh::new("filter".to_string(), false, Option::None));
+    // Recreate every render target and history texture with the new window size
+    pub unsafe fn resize(&mut self, screen_size: (i32, i32)) {
+        if screen_size.0 <= 0 || screen_size.1 <= 0 {
+            return;
+        }
+        self.window_size = screen_size;
+        self.resize_render_targets();
+    }
 
-        self.previous_position_texture =
-            Some(Texture2D::new(TextureParameter::new_float_parameter((
-                gl_context.window().inner_size().width as i32,
-                gl_context.window().inner_size().height as i32,
-            ))));
+    // The new history textures hold no lighting yet, the accumulation starts again
+    unsafe fn resize_render_targets(&mut self) {
+        let render_size = self.render_size();
+        if let Some(render_graph) = self.render_graph.as_mut() {
+            render_graph.resize(render_size, self.window_size);
+        }
+        self.reset_accumulation = true;
+    }
 
-        self.previous_lighting_texture =
-            Some(Texture2D::new(TextureParameter::new_float_parameter((
-                gl_context.window().inner_size().width as i32,
-                gl_context.window().inner_size().height as i32,
-            ))));
+    fn render_size(&self) -> (i32, i32) {
+        self.render_scale.render_size(self.window_size)
     }
 
     pub fn update(&mut self, input_manager: &mut InputManager) {
-        // Shaders are recompiled if F5 is pressed
-        if input_manager.is_pressed_once(VirtualKeyCode::F5) {
+        // Reload the shaders from the assets
+        if input_manager.is_action_pressed_once(Action::ReloadShaders) {
             unsafe {
-                self.raymarching_quad.as_mut().unwrap().shader.compile();
-                self.lighting_quad.as_mut().unwrap().shader.compile();
-                self.filter_quad.as_mut().unwrap().shader.compile();
+                self.render_graph.as_mut().unwrap().recompile();
+                if let Err(error) = self.overlay.as_mut().unwrap().compile() {
+                    println!("Shader of the overlay: {}", error);
+                }
             }
         }
+        // Change the render scale or toggle its automatic mode
+        let mut render_scale_changed = false;
+        if input_manager.is_action_pressed_once(Action::RenderScaleUp) {
+            render_scale_changed = self.render_scale.increase();
+        }
+        if input_manager.is_action_pressed_once(Action::RenderScaleDown) {
+            render_scale_changed = self.render_scale.decrease();
+        }
+        if input_manager.is_action_pressed_once(Action::AutomaticRenderScale) {
+            self.render_scale.toggle_automatic();
+            println!(
+                "Automatic render scale {}",
+                if self.render_scale.is_automatic() {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+        }
+        if render_scale_changed {
+            self.on_render_scale_changed();
+        }
+        self.hud.update(input_manager);
+        // Open the render settings panel
+        if input_manager.is_action_pressed_once(Action::RenderSettings) {
+            self.gui_visible = !self.gui_visible;
+        }
     }
 
-    pub unsafe fn draw(
-        &self,
-        gl_context: &ContextWrapper<PossiblyCurrent, Window>,
-        player: &Player,
-        time_since_beginning: f32,
-        world_data: &WorldData,
-        bloc_size: Vector2<f32>,
-    ) {
-        // Copy old texture to new texture
-        Texture2D::copy(
-            self.raymarching_quad
-                .as_ref()
-                .expect("Can't find mesh raymarching_quad")
-                .shader
-                .fbo
-                .as_ref()
-                .expect("Can't find fbo raymarching_quad")
-                .texture
-                .id,
-            self.previous_position_texture.as_ref().unwrap().id,
-            (
-                gl_context.window().inner_size().width as i32,
-                gl_context.window().inner_size().height as i32,
-            ),
-        );
-
-        Texture2D::copy(
-            self.lighting_quad
-                .as_ref()
-                .expect("Can't find mesh lighting_quad")
-                .shader
-                .fbo
-                .as_ref()
-                .expect("Can't find fbo lighting_quad")
-                .texture
-                .id,
-            self.previous_lighting_texture.as_ref().unwrap().id,
-            (
-                gl_context.window().inner_size().width as i32,
-                gl_context.window().inner_size().height as i32,
-            ),
-        );
+    // The mouse is used by the settings panel instead of the camera while it is open
+    pub fn is_gui_visible(&self) -> bool {
+        self.gui_visible
+    }
 
-        // First pass : draw image in a fbo
-        self.setup_uniforms_draw_pass_1(player, world_data, bloc_size);
-        self.draw_pass_1(gl_context);
+    pub fn render_settings(&self) -> &RenderSettings {
+        &self.render_settings
+    }
 
-        // Second pass: use position draw (draw pass 1) to calculate lighting
-        self.setup_uniforms_draw_pass_2(player, time_since_beginning, bloc_size);
-        self.draw_pass_2(gl_context);
+    pub fn set_render_settings(&mut self, render_settings: RenderSettings) {
+        self.render_settings = render_settings;
+        self.reset_accumulation = true;
+    }
 
-        // Third pass: use everything drawn before and render final image
-        self.setup_uniforms_draw_pass_3(time_since_beginning, world_data, bloc_size);
-        self.draw_pass_3(gl_context);
+    fn on_render_scale_changed(&mut self) {
+        println!("Render scale: {}", self.render_scale.scale());
+        unsafe {
+            self.resize_render_targets();
+        }
     }
 
-    pub unsafe fn setup_uniforms_draw_pass_1(
-        &self,
+    pub unsafe fn draw(
+        &mut self,
         player: &Player,
+        time_since_beginning: f32,
         world_data: &WorldData,
         bloc_size: Vector2<f32>,
     ) {
-        // Send orientation inversed matrix to shader
-        self.raymarching_quad.as_ref().unwrap().send_uniform_mat4(
-            "invert_mvp",
-            player
-                .get_uniform()
-                .orientation_inversed
-                .iter()
-                .flatten()
-                .copied()
-                .collect::<Vec<f32>>()
-                .try_into()
-                .unwrap(),
+        let render_graph = self.render_graph.as_mut().unwrap();
+        Self::setup_uniforms(
+            render_graph,
+            player,
+            time_since_beginning,
+            world_data,
+            bloc_size,
         );
+        for (name, value) in self.render_settings.uniforms() {
+            render_graph.set_uniform(name, value);
+        }
+        if player.camera.is_orthographic() != self.orthographic {
+            self.orthographic = player.camera.is_orthographic();
+            self.reset_accumulation = true;
+        }
+        render_graph.set_uniform(
+            "reset_accumulation",
+            UniformValue::Int(self.reset_accumulation as i32),
+        );
+        self.reset_accumulation = false;
+        render_graph.execute();
 
-        // Send camera position to shader
-        self.raymarching_quad
-            .as_ref()
-            .unwrap()
-            .send_uniform_vec3("camera_position", player.get_eye_position().into());
+        // The timer queries give the GPU time of a frame rendered a few frames ago
+        if self.render_scale.record_frame_time(render_graph.gpu_time()) {
+            self.on_render_scale_changed();
+        }
+    }
 
-        self.raymarching_quad
+    pub fn pass_times(&self) -> Vec<(String, f32)> {
+        self.render_graph
             .as_ref()
-            .unwrap()
-            .send_uniform_f32("VOXEL_SIZE_XY", bloc_size.x);
-        self.raymarching_quad
+            .map_or(Vec::new(), |render_graph| render_graph.pass_times())
+    }
+
+    // Number of lighting samples accumulated at the center of the screen
+    pub fn accumulated_samples(&self) -> f32 {
+        self.pixel_readback
             .as_ref()
-            .unwrap()
-            .send_uniform_f32("VOXEL_SIZE_Z", bloc_size.y);
+            .and_then(|pixel_readback| pixel_readback.value("lighting"))
+            .map_or(0.0, |value| value[3])
+    }
 
-        // Send 3D world data uniform
-        let world_data_texture_name = CString::new("world_data_texture").unwrap();
-        let world_data_texture_location = gl::GetUniformLocation(
-            self.raymarching_quad.as_ref().unwrap().shader.id,
-            world_data_texture_name.as_ptr(),
-        );
+    // Copy the pixels read by the HUD and the statistics, their values are read on a later
+    // frame once the GPU is done
+    unsafe fn read_pixels(&mut self, cursor_position: Option<(f32, f32)>) {
+        let render_size = self.render_size();
+        let (Some(render_graph), Some(pixel_readback)) =
+            (self.render_graph.as_ref(), self.pixel_readback.as_mut())
+        else {
+            return;
+        };
+        pixel_readback.update();
+        let mut targets = vec![("lighting", (render_size.0 / 2, render_size.1 / 2))];
+        match cursor_position.filter(|_| self.hud.is_visible()) {
+            Some(cursor_position) => {
+                let pixel = Self::cursor_pixel(render_size, self.window_size, cursor_position);
+                targets.extend(CURSOR_TARGETS.map(|name| (name, pixel)));
+            }
+            None => {
+                for name in CURSOR_TARGETS {
+                    pixel_readback.forget(name);
+                }
+            }
+        }
+        pixel_readback.request(render_graph, &targets);
+    }
 
-        // Activate texture world data
-        gl::ActiveTexture(gl::TEXTURE1);
-        world_data.bind_texture();
-        self.raymarching_quad.as_ref().unwrap().shader.apply();
-        gl::Uniform1i(world_data_texture_location, 1);
-        self.raymarching_quad.as_ref().unwrap().shader.stop();
-        gl::ActiveTexture(gl::TEXTURE0);
+    pub fn texture_memory(&self) -> usize {
+        self.render_graph
+            .as_ref()
+            .map_or(0, |render_graph| render_graph.texture_memory())
     }
 
-    pub unsafe fn setup_uniforms_draw_pass_2(
-        &self,
+    // Values shared by every pass, each pass only sends the uniforms it declares
+    fn setup_uniforms(
+        render_graph: &mut RenderGraph,
         player: &Player,
         time_since_beginning: f32,
+        world_data: &WorldData,
         bloc_size: Vector2<f32>,
     ) {
-        // Send previous mvp matrix to shader
-        self.lighting_quad.as_ref().unwrap().send_uniform_mat4(
+        let camera_uniform = player.get_uniform();
+        render_graph.set_uniform(
+            "mvp",
+            UniformValue::Mat4(Self::matrix_to_array(camera_uniform.mvp)),
+        );
+        render_graph.set_uniform(
+            "invert_mvp",
+            UniformValue::Mat4(Self::matrix_to_array(camera_uniform.orientation_inversed)),
+        );
+        render_graph.set_uniform(
+            "invert_mvp_ortho",
+            UniformValue::Mat4(Self::matrix_to_array(
+                camera_uniform.orientation_inversed_ortho,
+            )),
+        );
+        render_graph.set_uniform(
+            "camera_forward",
+            UniformValue::Vec3(player.camera.get_forward().into()),
+        );
+        render_graph.set_uniform(
+            "orthographic",
+            UniformValue::Int(player.camera.is_orthographic() as i32),
+        );
+        render_graph.set_uniform(
+            "max_ray_distance",
+            UniformValue::Float(Self::max_ray_distance(player, world_data)),
+        );
+        render_graph.set_uniform(
             "previous_mvp",
-            player
-                .get_uniform()
-                .previous_mvp
-                .iter()
-                .flatten()
-                .copied()
-                .collect::<Vec<f32>>()
-                .try_into()
-                .unwrap(),
+            UniformValue::Mat4(Self::matrix_to_array(camera_uniform.previous_mvp)),
         );
-
-        // Sending time to shader
-        self.lighting_quad
-            .as_ref()
-            .unwrap()
-            .send_uniform_f32("time", time_since_beginning);
-
-        self.lighting_quad
-            .as_ref()
-            .unwrap()
-            .send_uniform_f32("VOXEL_SIZE_XY", bloc_size.x);
-        self.lighting_quad
-            .as_ref()
-            .unwrap()
-            .send_uniform_f32("VOXEL_SIZE_Z", bloc_size.y);
-
-        // Sending camera position to shader
-        self.lighting_quad.as_ref().unwrap().send_uniform_vec3(
+        render_graph.set_uniform(
             "camera_position",
-            [
-                player.get_eye_position().x,
-                player.get_eye_position().y,
-                player.get_eye_position().z,
-            ],
+            UniformValue::Vec3(player.get_eye_position().into()),
         );
-
-        // Sending previous lighting texture to shader
-        let previous_lighting_texture_name = CString::new("previous_lighting_texture").unwrap();
-        let previous_lighting_texture_location = gl::GetUniformLocation(
-            self.lighting_quad.as_ref().unwrap().shader.id,
-            previous_lighting_texture_name.as_ptr(),
+        render_graph.set_uniform("time", UniformValue::Float(time_since_beginning));
+        render_graph.set_uniform("VOXEL_SIZE_XY", UniformValue::Float(bloc_size.x));
+        render_graph.set_uniform("VOXEL_SIZE_Z", UniformValue::Float(bloc_size.y));
+
+        // Levels of detail and bricks of a streamed volume
+        render_graph.set_uniform(
+            "LOD_DISTANCE",
+            UniformValue::Float(world_data.lod_distance()),
         );
-        gl::ActiveTexture(gl::TEXTURE1);
-        gl::BindTexture(
-            gl::TEXTURE_2D,
-            self.previous_lighting_texture.as_ref().unwrap().id,
+        render_graph.set_uniform(
+            "MAX_LOD_LEVEL",
+            UniformValue::Float(world_data.max_lod_level() as f32),
         );
-        self.lighting_quad.as_ref().unwrap().shader.apply();
-        gl::Uniform1i(previous_lighting_texture_location, 1);
-        self.lighting_quad.as_ref().unwrap().shader.stop();
-
-        // Sending previous position texture to shader
-        let previous_position_texture_name = CString::new("previous_position_texture").unwrap();
-        let previous_position_texture_location = gl::GetUniformLocation(
-            self.lighting_quad.as_ref().unwrap().shader.id,
-            previous_position_texture_name.as_ptr(),
+        render_graph.set_uniform(
+            "PREVIEW_LOD_LEVEL",
+            UniformValue::Float(world_data.preview_lod_level() as f32),
         );
-        gl::ActiveTexture(gl::TEXTURE2);
-        gl::BindTexture(
-            gl::TEXTURE_2D,
-            self.previous_position_texture.as_ref().unwrap().id,
+        render_graph.set_uniform(
+            "use_paging",
+            UniformValue::Int(world_data.is_streamed() as i32),
         );
-        self.lighting_quad.as_ref().unwrap().shader.apply();
-        gl::Uniform1i(previous_position_texture_location, 2);
-        self.lighting_quad.as_ref().unwrap().shader.stop();
-
-        // Sending current position texture to shader
-        let current_position_texture = self
-            .raymarching_quad
-            .as_ref()
-            .expect("Can't find mesh raymarching_quad")
-            .shader
-            .fbo
-            .as_ref()
-            .expect("Can't find fbo raymarching_quad")
-            .texture
-            .id;
-
-        let current_position_texture_name = CString::new("current_position_texture").unwrap();
-        let current_position_texture_location = gl::GetUniformLocation(
-            self.lighting_quad.as_ref().unwrap().shader.id,
-            current_position_texture_name.as_ptr(),
+        render_graph.set_uniform(
+            "BRICK_COUNT",
+            UniformValue::Float(world_data.bricks_per_axis() as f32),
         );
-        gl::ActiveTexture(gl::TEXTURE3);
-        gl::BindTexture(gl::TEXTURE_2D, current_position_texture);
-        self.lighting_quad.as_ref().unwrap().shader.apply();
-        gl::Uniform1i(current_position_texture_location, 3);
-        self.lighting_quad.as_ref().unwrap().shader.stop();
-        gl::ActiveTexture(gl::TEXTURE0);
-    }
-
-    pub unsafe fn setup_uniforms_draw_pass_3(
-        &self,
-        time_since_beginning: f32,
-        world_data: &WorldData,
-        bloc_size: Vector2<f32>,
-    ) {
-        // Sending time to shader
-        self.filter_quad
-            .as_ref()
-            .unwrap()
-            .send_uniform_f32("time", time_since_beginning);
-
-        self.filter_quad
-            .as_ref()
-            .unwrap()
-            .send_uniform_f32("VOXEL_SIZE_XY", bloc_size.x);
-        self.filter_quad
-            .as_ref()
-            .unwrap()
-            .send_uniform_f32("VOXEL_SIZE_Z", bloc_size.y);
-
-        let world_data_texture_name = CString::new("world_data_texture").unwrap();
-        let world_data_texture_location = gl::GetUniformLocation(
-            self.raymarching_quad.as_ref().unwrap().shader.id,
-            world_data_texture_name.as_ptr(),
+        render_graph.set_uniform(
+            "GRID_SIZE",
+            UniformValue::Float(world_data.grid_size() as f32),
+        );
+        render_graph.set_uniform(
+            "ATLAS_BRICK_COUNT",
+            UniformValue::Float(world_data.atlas_bricks_per_axis() as f32),
         );
 
-        // Activate texture world data
-        gl::ActiveTexture(gl::TEXTURE1);
-        world_data.bind_texture();
-        self.filter_quad.as_ref().unwrap().shader.apply();
-        gl::Uniform1i(world_data_texture_location, 1);
-        self.filter_quad.as_ref().unwrap().shader.stop();
-
-        // Sending current lighting texture
-        let current_lighting_texture_name = CString::new("current_lighting_texture").unwrap();
-        let current_lighting_texture_location = gl::GetUniformLocation(
-            self.filter_quad.as_ref().unwrap().shader.id,
-            current_lighting_texture_name.as_ptr(),
+        render_graph.set_external_texture("world_data", gl::TEXTURE_3D, world_data.texture_id());
+        render_graph.set_external_texture(
+            "occupancy",
+            gl::TEXTURE_3D,
+            world_data.occupancy_texture_id(),
         );
-        gl::ActiveTexture(gl::TEXTURE2);
-        gl::BindTexture(
-            gl::TEXTURE_2D,
-            self.lighting_quad
-                .as_ref()
-                .unwrap()
-                .shader
-                .fbo
-                .as_ref()
-                .unwrap()
-                .texture
-                .id,
+        render_graph.set_external_texture(
+            "macro_occupancy",
+            gl::TEXTURE_3D,
+            world_data.macro_occupancy_texture_id(),
         );
-        self.filter_quad.as_ref().unwrap().shader.apply();
-        gl::Uniform1i(current_lighting_texture_location, 2);
-        self.filter_quad.as_ref().unwrap().shader.stop();
-
-        // Sending current position texture
-        let current_position_texture_name = CString::new("current_position_texture").unwrap();
-        let current_position_texture_location = gl::GetUniformLocation(
-            self.filter_quad.as_ref().unwrap().shader.id,
-            current_position_texture_name.as_ptr(),
+        render_graph.set_external_texture(
+            "brick_atlas",
+            gl::TEXTURE_3D,
+            world_data.atlas_texture_id(),
         );
-        gl::ActiveTexture(gl::TEXTURE3);
-        gl::BindTexture(
-            gl::TEXTURE_2D,
-            self.raymarching_quad
-                .as_ref()
-                .unwrap()
-                .shader
-                .fbo
-                .as_ref()
-                .unwrap()
-                .texture
-                .id,
+        render_graph.set_external_texture(
+            "indirection",
+            gl::TEXTURE_3D,
+            world_data.indirection_texture_id(),
         );
-        self.filter_quad.as_ref().unwrap().shader.apply();
-        gl::Uniform1i(current_position_texture_location, 3);
-        self.filter_quad.as_ref().unwrap().shader.stop();
-        gl::ActiveTexture(gl::TEXTURE0);
     }
 
-    pub unsafe fn draw_pass_1(&self, gl_context: &ContextWrapper<PossiblyCurrent, Window>) {
-        gl::Viewport(
-            0,
-            0,
-            (gl_context.window().inner_size().width) as i32,
-            (gl_context.window().inner_size().height) as i32,
-        );
-        self.raymarching_quad.as_ref().unwrap().draw();
+    // Rays stop once past the farthest corner of the volume, or at the distance set in the
+    // camera settings if it is shorter
+    fn max_ray_distance(player: &Player, world_data: &WorldData) -> f32 {
+        let eye_position = player.get_eye_position();
+        let extent = world_data.extent();
+        let mut farthest_corner = Vector3::new(0.0, 0.0, 0.0);
+        for axis in 0..3 {
+            farthest_corner[axis] = if eye_position[axis] < 0.5 * extent[axis] {
+                extent[axis]
+            } else {
+                0.0
+            };
+        }
+        let mut volume_distance = (farthest_corner - eye_position).magnitude();
+        // Orthographic rays start up to half the view plane away from the camera
+        if player.camera.is_orthographic() {
+            volume_distance += 0.5 * player.camera.get_ortho_size().magnitude();
+        }
+        volume_distance.min(player.camera.get_projection().max_ray_distance)
     }
 
-    pub unsafe fn draw_pass_2(&self, gl_context: &ContextWrapper<PossiblyCurrent, Window>) {
-        gl::Viewport(
-            0,
-            0,
-            (gl_context.window().inner_size().width) as i32,
-            (gl_context.window().inner_size().height) as i32,
-        );
-        self.lighting_quad.as_ref().unwrap().draw();
+    fn matrix_to_array(matrix: [[f32; 4]; 4]) -> [f32; 16] {
+        let matrix: Matrix4<f32> = matrix.into();
+        let array: &[f32; 16] = matrix.as_ref();
+        *array
     }
 
-    pub unsafe fn draw_pass_3(&self, gl_context: &ContextWrapper<PossiblyCurrent, Window>) {
-        gl::Viewport(
-            0,
-            0,
-            (gl_context.window().inner_size().width) as i32,
-            (gl_context.window().inner_size().height) as i32,
+    // HUD, settings panel and, while a shader does not compile, its compiler log at the top
+    // of the window
+    pub unsafe fn draw_overlay(
+        &mut self,
+        file_name: &str,
+        player: &Player,
+        world_data: &WorldData,
+        input_manager: &mut InputManager,
+    ) {
+        let cursor_position = input_manager.cursor_position();
+        self.read_pixels(cursor_position);
+        let Some(overlay) = self.overlay.as_mut() else {
+            return;
+        };
+        if self.hud.is_visible() {
+            let hud_info = HudInfo {
+                file_name,
+                spacing: world_data.spacing,
+                camera_forward: player.camera.get_forward(),
+                camera_up: player.camera.get_up(),
+                cursor_sample: self.pixel_readback.as_ref().and_then(Self::cursor_sample),
+                bindings: input_manager.bindings(),
+            };
+            self.hud.draw(overlay, self.window_size, &hud_info);
+        }
+        if self.gui_visible {
+            let mut panel = self.gui.begin(
+                overlay,
+                input_manager,
+                (
+                    self.window_size.0 as f32 - SETTINGS_PANEL_WIDTH - 10.0,
+                    10.0,
+                ),
+                SETTINGS_PANEL_WIDTH,
+            );
+            if self
+                .render_settings
+                .edit(&mut panel, &self.default_render_settings)
+            {
+                self.reset_accumulation = true;
+            }
+            panel.end();
+        }
+        if let Some(render_graph) = self.render_graph.as_ref() {
+            Hud::draw_shader_errors(overlay, self.window_size, render_graph.shader_errors());
+        }
+        overlay.draw(self.window_size);
+    }
+
+    // Pixel of the render targets under the cursor
+    fn cursor_pixel(
+        render_size: (i32, i32),
+        window_size: (i32, i32),
+        cursor_position: (f32, f32),
+    ) -> (i32, i32) {
+        (
+            ((cursor_position.0 / window_size.0 as f32 * render_size.0 as f32) as i32)
+                .clamp(0, render_size.0 - 1),
+            (((1.0 - cursor_position.1 / window_size.1 as f32) * render_size.1 as f32) as i32)
+                .clamp(0, render_size.1 - 1),
+        )
+    }
+
+    // G-buffer under the cursor, None if no voxel is hit there
+    fn cursor_sample(pixel_readback: &PixelReadback) -> Option<CursorSample> {
+        // Alpha of the position target is 1 + level on a hit
+        let position = pixel_readback.value("position")?;
+        if position[3] < 1.0 {
+            return None;
+        }
+        let voxel = pixel_readback.value("voxel")?;
+        let material = pixel_readback.value("material")?;
+        Some(CursorSample {
+            voxel: Vector3::new(voxel[0] as i32, voxel[1] as i32, voxel[2] as i32),
+            level: voxel[3],
+            intensity: material[0],
+            label: material[3] as u8,
+        })
+    }
+
+    // Horizontal bar at the bottom of the window, drawn with scissored clears
+    pub unsafe fn draw_progress_bar(
+        &self,
+        gl_context: &ContextWrapper<PossiblyCurrent, Window>,
+        progress: f32,
+    ) {
+        let window_size = gl_context.window().inner_size();
+        let margin = 20;
+        let bar_width = window_size.width as i32 - 2 * margin;
+        let bar_height = 12;
+
+        gl::Enable(gl::SCISSOR_TEST);
+        gl::Scissor(margin, margin, bar_width, bar_height);
+        gl::ClearColor(0.15, 0.15, 0.15, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::Scissor(
+            margin,
+            margin,
+            (bar_width as f32 * progress.clamp(0.0, 1.0)) as i32,
+            bar_height,
         );
-        self.filter_quad.as_ref().unwrap().draw();
+        gl::ClearColor(0.85, 0.85, 0.85, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::Disable(gl::SCISSOR_TEST);
     }
 }
@@ -4,36 +4,82 @@ use crate::graph::texture::Texture2D;
 
 use super::texture::TextureParameter;
 
+// Layout of the attachments of a FBO
+#[derive(Debug, Clone, Copy)]
+pub struct FboParameter {
+    pub texture_parameter: TextureParameter,
+    pub color_attachments: usize,
+    pub with_depth: bool,
+}
+
 pub struct FBO {
     pub fbo_id: GLuint,
-    pub texture: Texture2D,
+    pub textures: Vec<Texture2D>,
+    pub depth_texture: Option<Texture2D>,
+    fbo_parameter: FboParameter,
 }
 
 impl FBO {
-    pub unsafe fn new(texture_parameter: TextureParameter) -> Self {
+    pub unsafe fn new(fbo_parameter: FboParameter) -> Self {
         let mut fbo_id: GLuint = 0;
 
         gl::GenFramebuffers(1, &mut fbo_id);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo_id);
 
-        let texture = Texture2D::new(texture_parameter);
+        // Fragment output `i` is written in color attachment `i`
+        let mut textures = Vec::with_capacity(fbo_parameter.color_attachments);
+        let mut draw_buffers = Vec::with_capacity(fbo_parameter.color_attachments);
+        for index in 0..fbo_parameter.color_attachments {
+            let texture = Texture2D::new(fbo_parameter.texture_parameter);
+            let attachment = gl::COLOR_ATTACHMENT0 + index as u32;
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, gl::TEXTURE_2D, texture.id, 0);
+            textures.push(texture);
+            draw_buffers.push(attachment);
+        }
+        gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
 
-        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo_id);
-        gl::FramebufferTexture2D(
-            gl::FRAMEBUFFER,
-            gl::COLOR_ATTACHMENT0,
-            gl::TEXTURE_2D,
-            texture.id,
-            0,
-        );
+        let depth_texture = if fbo_parameter.with_depth {
+            let depth_texture = Texture2D::new(TextureParameter::new_depth_parameter(
+                fbo_parameter.texture_parameter.screen_size,
+            ));
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::TEXTURE_2D,
+                depth_texture.id,
+                0,
+            );
+            Some(depth_texture)
+        } else {
+            None
+        };
+
+        // Texture content is undefined after creation
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::ClearDepth(1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
         gl::BindTexture(gl::TEXTURE_2D, 0);
         gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
 
         Self {
             fbo_id: fbo_id,
-            texture: texture,
+            textures,
+            depth_texture,
+            fbo_parameter,
         }
     }
 
+    // Recreate the attachments with a new size, previous content is lost
+    pub unsafe fn resize(&mut self, screen_size: (i32, i32)) {
+        *self = Self::new(FboParameter {
+            texture_parameter: TextureParameter {
+                screen_size,
+                ..self.fbo_parameter.texture_parameter
+            },
+            ..self.fbo_parameter
+        });
+    }
+
     pub unsafe fn bind(&self) {
         gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo_id);
     }
@@ -42,3 +88,11 @@ impl FBO {
         gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
     }
 }
+
+impl Drop for FBO {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, [self.fbo_id].as_ptr());
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use gl::types::{GLint, GLuint};
+
+// Results are read a few frames later so the CPU never waits for the GPU
+const QUERIES_IN_FLIGHT: usize = 3;
+
+// GPU time spent between `begin` and `end`, measured with timer queries.
+// Timers can't be nested: only one timer may be running at a time.
+pub struct GpuTimer {
+    queries: [GLuint; QUERIES_IN_FLIGHT],
+    pending: [bool; QUERIES_IN_FLIGHT],
+    frame: usize,
+    // Last measured time in seconds
+    elapsed_time: f32,
+}
+
+impl GpuTimer {
+    pub unsafe fn new() -> Self {
+        let mut queries = [0; QUERIES_IN_FLIGHT];
+        gl::GenQueries(QUERIES_IN_FLIGHT as i32, queries.as_mut_ptr());
+        Self {
+            queries,
+            pending: [false; QUERIES_IN_FLIGHT],
+            frame: 0,
+            elapsed_time: 0.0,
+        }
+    }
+
+    pub unsafe fn begin(&mut self) {
+        let slot = self.frame % QUERIES_IN_FLIGHT;
+        // The query is reused: read the result it holds from an older frame first. If the GPU
+        // is still behind, that result is dropped and the previous time is kept.
+        if self.pending[slot] {
+            let mut available: GLint = 0;
+            gl::GetQueryObjectiv(
+                self.queries[slot],
+                gl::QUERY_RESULT_AVAILABLE,
+                &mut available,
+            );
+            if available != 0 {
+                let mut elapsed_nanoseconds: u64 = 0;
+                gl::GetQueryObjectui64v(
+                    self.queries[slot],
+                    gl::QUERY_RESULT,
+                    &mut elapsed_nanoseconds,
+                );
+                self.elapsed_time = elapsed_nanoseconds as f32 * 1e-9;
+            }
+            self.pending[slot] = false;
+        }
+        gl::BeginQuery(gl::TIME_ELAPSED, self.queries[slot]);
+    }
+
+    pub unsafe fn end(&mut self) {
+        gl::EndQuery(gl::TIME_ELAPSED);
+        self.pending[self.frame % QUERIES_IN_FLIGHT] = true;
+        self.frame += 1;
+    }
+
+    pub fn elapsed_time(&self) -> f32 {
+        self.elapsed_time
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteQueries(QUERIES_IN_FLIGHT as i32, self.queries.as_ptr());
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::ops::RangeInclusive;
+
+use glutin::event::MouseButton;
+
+use crate::io::input_player::InputManager;
+
+use super::overlay::{Overlay, GLYPH_SIZE};
+
+const PADDING: f32 = 8.0;
+const ROW_HEIGHT: f32 = GLYPH_SIZE.1 + 6.0;
+// Width of the labels on the left of the widgets, in characters
+const LABEL_COLUMNS: f32 = 15.0;
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const PANEL_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 0.85];
+const WIDGET_COLOR: [f32; 4] = [0.25, 0.25, 0.25, 1.0];
+const HOVERED_WIDGET_COLOR: [f32; 4] = [0.35, 0.35, 0.35, 1.0];
+const FILL_COLOR: [f32; 4] = [0.2, 0.45, 0.75, 1.0];
+
+// State of the immediate mode GUI kept between frames. Widgets are declared every frame
+// on a `GuiPanel` and return whether the user changed their value.
+pub struct Gui {
+    // Index of the slider being dragged
+    active_widget: Option<usize>,
+    // Height of the panel at the previous frame, to draw its background before the widgets
+    panel_height: f32,
+}
+
+pub struct GuiPanel<'a> {
+    gui: &'a mut Gui,
+    overlay: &'a mut Overlay,
+    cursor_position: Option<(f32, f32)>,
+    mouse_down: bool,
+    mouse_clicked: bool,
+    position: (f32, f32),
+    width: f32,
+    row: usize,
+    widget_index: usize,
+}
+
+impl Gui {
+    pub fn new() -> Self {
+        Self {
+            active_widget: None,
+            panel_height: 0.0,
+        }
+    }
+
+    // Start a panel whose top left corner is at `position`, in pixels
+    pub fn begin<'a>(
+        &'a mut self,
+        overlay: &'a mut Overlay,
+        input_manager: &mut InputManager,
+        position: (f32, f32),
+        width: f32,
+    ) -> GuiPanel<'a> {
+        let mouse_down = input_manager.is_mouse_pressed(MouseButton::Left);
+        if !mouse_down {
+            self.active_widget = None;
+        }
+        overlay.rectangle(position, (width, self.panel_height), PANEL_COLOR);
+        GuiPanel {
+            cursor_position: input_manager.cursor_position(),
+            mouse_down,
+            mouse_clicked: input_manager.is_mouse_pressed_once(MouseButton::Left),
+            gui: self,
+            overlay,
+            position,
+            width,
+            row: 0,
+            widget_index: 0,
+        }
+    }
+}
+
+impl GuiPanel<'_> {
+    pub fn end(self) {
+        self.gui.panel_height = self.row as f32 * ROW_HEIGHT + 2.0 * PADDING;
+    }
+
+    pub fn title(&mut self, text: &str) {
+        let (x, y) = self.next_row();
+        self.overlay.text((x, y), text, TEXT_COLOR);
+    }
+
+    // Return true when the value changed
+    pub fn slider(&mut self, label: &str, value: &mut f32, range: RangeInclusive<f32>) -> bool {
+        let index = self.next_widget();
+        let (position, size) = self.widget_area(label);
+        let (minimum, maximum) = (*range.start(), *range.end());
+
+        if self.is_clicked(position, size) {
+            self.gui.active_widget = Some(index);
+        }
+        let mut changed = false;
+        if self.gui.active_widget == Some(index) && self.mouse_down {
+            if let Some(cursor_position) = self.cursor_position {
+                let ratio = ((cursor_position.0 - position.0) / size.0).clamp(0.0, 1.0);
+                let new_value = minimum + ratio * (maximum - minimum);
+                changed = new_value != *value;
+                *value = new_value;
+            }
+        }
+
+        let ratio = ((*value - minimum) / (maximum - minimum)).clamp(0.0, 1.0);
+        self.overlay
+            .rectangle(position, size, self.widget_color(position, size));
+        self.overlay
+            .rectangle(position, (ratio * size.0, size.1), FILL_COLOR);
+        self.centered_text(position, size, &format!("{:.3}", value));
+        changed
+    }
+
+    pub fn checkbox(&mut self, label: &str, value: &mut bool) -> bool {
+        self.next_widget();
+        let (position, size) = self.widget_area(label);
+        let changed = self.is_clicked(position, size);
+        if changed {
+            *value = !*value;
+        }
+        self.overlay
+            .rectangle(position, size, self.widget_color(position, size));
+        self.centered_text(position, size, if *value { "on" } else { "off" });
+        changed
+    }
+
+    // Each click selects the next option
+    pub fn selector(&mut self, label: &str, selected: &mut usize, options: &[&str]) -> bool {
+        self.next_widget();
+        let (position, size) = self.widget_area(label);
+        let changed = self.is_clicked(position, size);
+        if changed {
+            *selected = (*selected + 1) % options.len();
+        }
+        self.overlay
+            .rectangle(position, size, self.widget_color(position, size));
+        self.centered_text(position, size, &format!("< {} >", options[*selected]));
+        changed
+    }
+
+    pub fn button(&mut self, label: &str) -> bool {
+        self.next_widget();
+        let (x, y) = self.next_row();
+        let position = (x, y - 2.0);
+        let size = (self.width - 2.0 * PADDING, ROW_HEIGHT - 2.0);
+        let clicked = self.is_clicked(position, size);
+        self.overlay
+            .rectangle(position, size, self.widget_color(position, size));
+        self.centered_text(position, size, label);
+        clicked
+    }
+
+    fn next_widget(&mut self) -> usize {
+        self.widget_index += 1;
+        self.widget_index
+    }
+
+    // Top left corner of the content of the next row
+    fn next_row(&mut self) -> (f32, f32) {
+        let row_position = (
+            self.position.0 + PADDING,
+            self.position.1 + PADDING + self.row as f32 * ROW_HEIGHT + 3.0,
+        );
+        self.row += 1;
+        row_position
+    }
+
+    // Write the label of a row and return the position and size of its widget
+    fn widget_area(&mut self, label: &str) -> ((f32, f32), (f32, f32)) {
+        let (x, y) = self.next_row();
+        self.overlay.text((x, y), label, TEXT_COLOR);
+        let label_width = LABEL_COLUMNS * GLYPH_SIZE.0;
+        (
+            (x + label_width, y - 2.0),
+            (self.width - 2.0 * PADDING - label_width, ROW_HEIGHT - 2.0),
+        )
+    }
+
+    fn centered_text(&mut self, position: (f32, f32), size: (f32, f32), text: &str) {
+        let text_size = Overlay::text_size(text);
+        self.overlay.text(
+            (
+                position.0 + 0.5 * (size.0 - text_size.0),
+                position.1 + 0.5 * (size.1 - text_size.1),
+            ),
+            text,
+            TEXT_COLOR,
+        );
+    }
+
+    fn is_hovered(&self, position: (f32, f32), size: (f32, f32)) -> bool {
+        self.cursor_position
+            .is_some_and(|cursor_position| Self::contains(position, size, cursor_position))
+    }
+
+    fn is_clicked(&self, position: (f32, f32), size: (f32, f32)) -> bool {
+        self.mouse_clicked && self.is_hovered(position, size)
+    }
+
+    fn widget_color(&self, position: (f32, f32), size: (f32, f32)) -> [f32; 4] {
+        if self.is_hovered(position, size) {
+            HOVERED_WIDGET_COLOR
+        } else {
+            WIDGET_COLOR
+        }
+    }
+
+    fn contains(position: (f32, f32), size: (f32, f32), point: (f32, f32)) -> bool {
+        point.0 >= position.0
+            && point.1 >= position.1
+            && point.0 < position.0 + size.0
+            && point.1 < position.1 + size.1
+    }
+}
@@ -0,0 +1,262 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+use crate::{
+    io::{
+        bindings::{Action, Bindings},
+        input_player::InputManager,
+    },
+    player::view_preset::PATIENT_AXES,
+};
+
+use super::overlay::{Overlay, GLYPH_SIZE};
+
+const MARGIN: f32 = 10.0;
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const HINT_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const ERROR_BACKGROUND_COLOR: [f32; 4] = [0.8, 0.1, 0.1, 0.9];
+// Lines of the compiler log shown in the window, the full log is printed in the terminal
+const MAX_ERROR_LINES: usize = 20;
+
+// Actions listed in the key hints with the inputs currently bound to them
+const KEY_HINTS: [(&[Action], &str); 28] = [
+    (
+        &[
+            Action::MoveForward,
+            Action::MoveLeft,
+            Action::MoveBackward,
+            Action::MoveRight,
+        ],
+        "move",
+    ),
+    (&[Action::MoveUp, Action::MoveDown], "up / down"),
+    (&[Action::MoveFaster], "faster"),
+    (&[Action::SpeedUp, Action::SpeedDown], "speed"),
+    (&[Action::Look], "look (hold)"),
+    (&[Action::ToggleLook], "look mode"),
+    (&[Action::ToggleOrbit], "orbit camera"),
+    (&[Action::ToggleOrthographic], "orthographic projection"),
+    (&[Action::OrbitRotate], "orbit rotate"),
+    (&[Action::OrbitPan], "orbit pan"),
+    (&[Action::ZoomIn, Action::ZoomOut], "orbit zoom"),
+    (&[Action::ResetView], "reset view"),
+    (&[Action::ViewAnterior], "anterior view"),
+    (&[Action::ViewPosterior], "posterior view"),
+    (&[Action::ViewLeft], "left view"),
+    (&[Action::ViewRight], "right view"),
+    (&[Action::ViewSuperior], "superior view"),
+    (&[Action::ViewInferior], "inferior view"),
+    (&Action::BOOKMARKS, "recall bookmark"),
+    (&[Action::SaveBookmark], "hold to save a bookmark"),
+    (
+        &[Action::RenderScaleUp, Action::RenderScaleDown],
+        "render scale",
+    ),
+    (&[Action::RenderSettings], "render settings"),
+    (&[Action::KeyHints], "key hints"),
+    (&[Action::HideOverlay], "hide overlay"),
+    (&[Action::Statistics], "statistics"),
+    (&[Action::ReloadShaders], "reload shaders"),
+    (&[Action::AutomaticRenderScale], "automatic render scale"),
+    (&[Action::Quit], "quit"),
+];
+
+// Voxel under the mouse cursor, read back from the G-buffer
+pub struct CursorSample {
+    pub voxel: Vector3<i32>,
+    pub level: f32,
+    pub intensity: f32,
+    pub label: u8,
+}
+
+// Values displayed by the HUD for the current frame
+pub struct HudInfo<'a> {
+    pub file_name: &'a str,
+    // Voxel size in millimetres, unknown for a generated volume
+    pub spacing: Option<Vector2<f32>>,
+    pub camera_forward: Vector3<f32>,
+    pub camera_up: Vector3<f32>,
+    pub cursor_sample: Option<CursorSample>,
+    pub bindings: &'a Bindings,
+}
+
+// File information, voxel under the cursor, orientation labels and key hints
+pub struct Hud {
+    visible: bool,
+    key_hints_visible: bool,
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Self {
+            visible: true,
+            key_hints_visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn update(&mut self, input_manager: &mut InputManager) {
+        if input_manager.is_action_pressed_once(Action::KeyHints) {
+            self.key_hints_visible = !self.key_hints_visible;
+        }
+        if input_manager.is_action_pressed_once(Action::HideOverlay) {
+            self.visible = !self.visible;
+        }
+    }
+
+    pub fn draw(&self, overlay: &mut Overlay, window_size: (i32, i32), hud_info: &HudInfo) {
+        if !self.visible {
+            return;
+        }
+        let window_size = (window_size.0 as f32, window_size.1 as f32);
+
+        let mut information = hud_info.file_name.to_string();
+        if let Some(spacing) = hud_info.spacing {
+            information.push_str(&format!(
+                "\nspacing {:.2} x {:.2} x {:.2} mm",
+                spacing.x, spacing.x, spacing.y
+            ));
+        }
+        if let Some(cursor_sample) = hud_info.cursor_sample.as_ref() {
+            information.push_str(&format!(
+                "\nvoxel {} {} {} (level {})\nintensity {:.0} label {}",
+                cursor_sample.voxel.x,
+                cursor_sample.voxel.y,
+                cursor_sample.voxel.z,
+                cursor_sample.level,
+                255.0 * cursor_sample.intensity,
+                cursor_sample.label
+            ));
+        }
+        Self::text_box(overlay, (MARGIN, MARGIN), &information, TEXT_COLOR);
+
+        Self::draw_orientation_labels(
+            overlay,
+            window_size,
+            hud_info.camera_forward,
+            hud_info.camera_up,
+        );
+
+        let hints = if self.key_hints_visible {
+            Self::key_hints(hud_info.bindings)
+        } else {
+            format!("{} key hints", hud_info.bindings.names(Action::KeyHints))
+        };
+        let hints_size = Overlay::text_size(&hints);
+        Self::text_box(
+            overlay,
+            (MARGIN, window_size.1 - MARGIN - hints_size.1),
+            &hints,
+            HINT_COLOR,
+        );
+    }
+
+    // One line per group of actions, with the inputs in a column on the left
+    fn key_hints(bindings: &Bindings) -> String {
+        let lines = KEY_HINTS
+            .iter()
+            .map(|(actions, description)| {
+                let names = actions
+                    .iter()
+                    .map(|action| bindings.names(*action))
+                    .collect::<Vec<String>>();
+                (names.join(", "), *description)
+            })
+            .collect::<Vec<(String, &str)>>();
+        let column_width = lines
+            .iter()
+            .map(|(names, _)| names.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut hints = lines
+            .iter()
+            .map(|(names, description)| {
+                format!("{:width$}  {}", names, description, width = column_width)
+            })
+            .collect::<Vec<String>>();
+        hints.push("Letter keys bound by scancode are named as on a QWERTY keyboard".to_string());
+        hints.join("\n")
+    }
+
+    // Anatomical direction at the middle of each border of the window, see `PATIENT_AXES`
+    fn draw_orientation_labels(
+        overlay: &mut Overlay,
+        window_size: (f32, f32),
+        camera_forward: Vector3<f32>,
+        camera_up: Vector3<f32>,
+    ) {
+        let right = camera_forward.cross(camera_up).normalize();
+        let up = right.cross(camera_forward).normalize();
+        let center = (0.5 * window_size.0, 0.5 * window_size.1);
+        let labels = [
+            (right, (window_size.0 - MARGIN - GLYPH_SIZE.0, center.1)),
+            (-right, (MARGIN, center.1)),
+            (up, (center.0, MARGIN)),
+            (-up, (center.0, window_size.1 - MARGIN - 2.0 * GLYPH_SIZE.1)),
+        ];
+        for (direction, position) in labels {
+            Self::text_box(
+                overlay,
+                position,
+                Self::orientation_label(direction),
+                TEXT_COLOR,
+            );
+        }
+    }
+
+    // Label of the volume axis closest to `direction`
+    fn orientation_label(direction: Vector3<f32>) -> &'static str {
+        let absolute = [direction.x.abs(), direction.y.abs(), direction.z.abs()];
+        let axis = if absolute[0] >= absolute[1] && absolute[0] >= absolute[2] {
+            0
+        } else if absolute[1] >= absolute[2] {
+            1
+        } else {
+            2
+        };
+        let labels = PATIENT_AXES[axis];
+        if direction[axis] >= 0.0 {
+            labels.0
+        } else {
+            labels.1
+        }
+    }
+
+    // Banner at the top of the window with the beginning of the compiler logs
+    pub fn draw_shader_errors(overlay: &mut Overlay, window_size: (i32, i32), errors: &[String]) {
+        if errors.is_empty() {
+            return;
+        }
+        let mut lines = errors
+            .iter()
+            .flat_map(|error| error.lines())
+            .collect::<Vec<&str>>();
+        if lines.len() > MAX_ERROR_LINES {
+            lines.truncate(MAX_ERROR_LINES);
+            lines.push("...");
+        }
+        let text = lines.join("\n");
+        let text_size = Overlay::text_size(&text);
+        overlay.rectangle(
+            (0.0, 0.0),
+            (window_size.0 as f32, text_size.1 + 2.0 * MARGIN),
+            ERROR_BACKGROUND_COLOR,
+        );
+        overlay.text((MARGIN, MARGIN), &text, TEXT_COLOR);
+    }
+
+    // Text on a translucent background so it stays readable over bright voxels
+    fn text_box(overlay: &mut Overlay, position: (f32, f32), text: &str, color: [f32; 4]) {
+        let text_size = Overlay::text_size(text);
+        let padding = 0.5 * GLYPH_SIZE.0;
+        overlay.rectangle(
+            (position.0 - padding, position.1 - padding),
+            (text_size.0 + 2.0 * padding, text_size.1 + 2.0 * padding),
+            BACKGROUND_COLOR,
+        );
+        overlay.text(position, text, color);
+    }
+}
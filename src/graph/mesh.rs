@@ -1,4 +1,9 @@
-use super::{buffers::Vertex, buffers::VAO, shader::Shader, texture::TextureParameter};
+use super::{
+    buffers::Vertex,
+    buffers::VAO,
+    fbo::FboParameter,
+    shader::{Shader, ShaderError},
+};
 
 pub struct Mesh {
     pub shader: Shader,
@@ -39,23 +44,18 @@ impl Mesh {
     pub unsafe fn new(
         shader_name: String,
         with_fbo: bool,
-        texture_parameter: Option<TextureParameter>,
+        fbo_parameter: Option<FboParameter>,
     ) -> Self {
         // Load shader
         let mut shader = Shader::new_without_fbo(CString::new(shader_name.clone()).unwrap());
         if with_fbo {
             shader = Shader::new_with_fbo(
                 CString::new(shader_name.clone()).unwrap().clone(),
-                texture_parameter.unwrap(),
+                fbo_parameter.unwrap(),
             );
         }
 
-        shader.compile();
         let vao = VAO::new(&BASIC_QUAD);
-        let pos_attrib = shader
-            .get_attrib_location("position")
-            .expect("can't get attrib location for position");
-        set_attribute!(vao, pos_attrib, Vertex::0);
 
         Self {
             shader: shader,
@@ -63,7 +63,20 @@ impl Mesh {
         }
     }
 
+    // Compile the shader and bind the quad vertices to its `position` attribute.
+    // The mesh is not drawn until its shader compiles.
+    pub unsafe fn compile(&mut self) -> Result<(), ShaderError> {
+        self.shader.compile()?;
+        let vao = &self.vao;
+        let pos_attrib = self.shader.get_attrib_location("position")?;
+        set_attribute!(vao, pos_attrib, Vertex::0);
+        Ok(())
+    }
+
     pub unsafe fn draw(&self) {
+        if !self.shader.is_compiled() {
+            return;
+        }
         self.shader.apply();
         self.vao.bind();
         gl::DrawArrays(
@@ -74,49 +87,4 @@ impl Mesh {
         self.vao.unbind();
         self.shader.stop();
     }
-
-    pub unsafe fn send_uniform_mat4(&self, attribute_name: &str, value: [f32; 16]) {
-        self.shader.apply();
-        let attribute_name_cstring = CString::new(attribute_name).unwrap();
-        let transform_location =
-            gl::GetUniformLocation(self.shader.id, attribute_name_cstring.as_ptr());
-        gl::UniformMatrix4fv(transform_location, 1, gl::FALSE, value.as_ptr());
-        self.shader.stop();
-    }
-
-    pub unsafe fn _send_uniform_vec2(&self, attribute_name: &str, value: [f32; 2]) {
-        self.shader.apply();
-        let attribute_name_cstring = CString::new(attribute_name).unwrap();
-        let transform_location =
-            gl::GetUniformLocation(self.shader.id, attribute_name_cstring.as_ptr());
-        gl::Uniform2fv(transform_location, 1, value.as_ptr());
-        self.shader.stop();
-    }
-
-    pub unsafe fn send_uniform_vec3(&self, attribute_name: &str, value: [f32; 3]) {
-        self.shader.apply();
-        let attribute_name_cstring = CString::new(attribute_name).unwrap();
-        let transform_location =
-            gl::GetUniformLocation(self.shader.id, attribute_name_cstring.as_ptr());
-        gl::Uniform3fv(transform_location, 1, value.as_ptr());
-        self.shader.stop();
-    }
-
-    pub unsafe fn _send_uniform_i32(&self, attribute_name: &str, value: i32) {
-        self.shader.apply();
-        let attribute_name_cstring = CString::new(attribute_name).unwrap();
-        let transform_location =
-            gl::GetUniformLocation(self.shader.id, attribute_name_cstring.as_ptr());
-        gl::Uniform1i(transform_location, value);
-        self.shader.stop();
-    }
-
-    pub unsafe fn send_uniform_f32(&self, attribute_name: &str, value: f32) {
-        self.shader.apply();
-        let attribute_name_cstring = CString::new(attribute_name).unwrap();
-        let transform_location =
-            gl::GetUniformLocation(self.shader.id, attribute_name_cstring.as_ptr());
-        gl::Uniform1f(transform_location, value);
-        self.shader.stop();
-    }
 }
@@ -1,6 +1,16 @@
 pub mod buffers;
 pub mod draw;
 pub mod fbo;
+pub mod gpu_timer;
+pub mod gui;
+pub mod hud;
 pub mod mesh;
+pub mod overlay;
+pub mod pixel_readback;
+pub mod render_graph;
+pub mod render_scale;
+pub mod render_settings;
 pub mod shader;
+pub mod shader_preprocessor;
+pub mod statistics;
 pub mod texture;
@@ -0,0 +1,205 @@
+use std::ffi::CString;
+
+use thiserror::Error;
+
+use crate::io::assets::read_binary_asset;
+
+use super::{
+    buffers::VAO,
+    shader::{Shader, ShaderError},
+    texture::{Texture2D, TextureParameter},
+};
+
+const FONT_PATH: &str = "fonts/font.png";
+// Size in pixels of a character of the font atlas. The atlas holds the ASCII characters
+// 32 to 127 on 16 columns, the last one being a full block used to draw rectangles.
+pub const GLYPH_SIZE: (f32, f32) = (8.0, 16.0);
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 6;
+const FIRST_CHARACTER: u32 = 32;
+const SOLID_CHARACTER: char = '\x7f';
+
+#[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
+pub enum OverlayError {
+    #[error("Can't read font: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Can't decode font: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("{0}")]
+    ShaderError(#[from] ShaderError),
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct OverlayVertex {
+    position: [f32; 2],
+    texture_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+// Text and rectangles drawn on top of the final image. Positions are in pixels from the
+// top left corner of the window, everything queued is drawn and cleared by `draw`.
+pub struct Overlay {
+    shader: Shader,
+    vao: VAO,
+    font_texture: Texture2D,
+    vertices: Vec<OverlayVertex>,
+}
+
+impl Overlay {
+    pub unsafe fn new() -> Result<Self, OverlayError> {
+        let font = image::load_from_memory(&read_binary_asset(FONT_PATH)?)?.to_luma8();
+        let font_parameter =
+            TextureParameter::new_red_byte_parameter((font.width() as i32, font.height() as i32));
+        let font_texture = Texture2D::new(font_parameter);
+        font_texture.set_data(font_parameter, font.as_raw());
+        // Glyphs are drawn pixel to pixel
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+
+        let mut overlay = Self {
+            shader: Shader::new_without_fbo(CString::new("overlay").unwrap()),
+            vao: VAO::new::<OverlayVertex>(&[]),
+            font_texture,
+            vertices: Vec::new(),
+        };
+        overlay.compile()?;
+        Ok(overlay)
+    }
+
+    pub unsafe fn compile(&mut self) -> Result<(), ShaderError> {
+        self.shader.compile()?;
+        let vao = &self.vao;
+        let position_attrib = self.shader.get_attrib_location("position")?;
+        crate::set_attribute!(vao, position_attrib, OverlayVertex::position);
+        let texture_coords_attrib = self.shader.get_attrib_location("texture_coords")?;
+        crate::set_attribute!(vao, texture_coords_attrib, OverlayVertex::texture_coords);
+        let color_attrib = self.shader.get_attrib_location("color")?;
+        crate::set_attribute!(vao, color_attrib, OverlayVertex::color);
+        Ok(())
+    }
+
+    // Size in pixels of a text, lines being separated by '\n'
+    pub fn text_size(text: &str) -> (f32, f32) {
+        let columns = text
+            .lines()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        (
+            columns as f32 * GLYPH_SIZE.0,
+            text.lines().count() as f32 * GLYPH_SIZE.1,
+        )
+    }
+
+    pub fn text(&mut self, position: (f32, f32), text: &str, color: [f32; 4]) {
+        for (line_index, line) in text.lines().enumerate() {
+            let y = position.1 + line_index as f32 * GLYPH_SIZE.1;
+            for (column, character) in line.chars().enumerate() {
+                if character == ' ' {
+                    continue;
+                }
+                let x = position.0 + column as f32 * GLYPH_SIZE.0;
+                self.glyph((x, y), GLYPH_SIZE, character, color);
+            }
+        }
+    }
+
+    pub fn rectangle(&mut self, position: (f32, f32), size: (f32, f32), color: [f32; 4]) {
+        // Sample the center of the block so the edges of the glyph are never read
+        let center = Self::glyph_texture_coords(SOLID_CHARACTER);
+        let center = [0.5 * (center[0] + center[2]), 0.5 * (center[1] + center[3])];
+        self.quad(
+            position,
+            size,
+            [center[0], center[1], center[0], center[1]],
+            color,
+        );
+    }
+
+    fn glyph(&mut self, position: (f32, f32), size: (f32, f32), character: char, color: [f32; 4]) {
+        // Characters missing from the atlas are replaced by '?'
+        let character = if (' '..=SOLID_CHARACTER).contains(&character) {
+            character
+        } else {
+            '?'
+        };
+        self.quad(position, size, Self::glyph_texture_coords(character), color);
+    }
+
+    // Left, top, right and bottom texture coordinates of a character of the atlas
+    fn glyph_texture_coords(character: char) -> [f32; 4] {
+        let index = character as u32 - FIRST_CHARACTER;
+        let column = (index % ATLAS_COLUMNS) as f32;
+        let row = (index / ATLAS_COLUMNS) as f32;
+        [
+            column / ATLAS_COLUMNS as f32,
+            row / ATLAS_ROWS as f32,
+            (column + 1.0) / ATLAS_COLUMNS as f32,
+            (row + 1.0) / ATLAS_ROWS as f32,
+        ]
+    }
+
+    fn quad(
+        &mut self,
+        position: (f32, f32),
+        size: (f32, f32),
+        texture_coords: [f32; 4],
+        color: [f32; 4],
+    ) {
+        let corner = |x: f32, y: f32, u: f32, v: f32| OverlayVertex {
+            position: [x, y],
+            texture_coords: [u, v],
+            color,
+        };
+        let (left, top) = position;
+        let (right, bottom) = (position.0 + size.0, position.1 + size.1);
+        let [u_left, v_top, u_right, v_bottom] = texture_coords;
+        self.vertices.extend_from_slice(&[
+            corner(left, bottom, u_left, v_bottom),
+            corner(right, bottom, u_right, v_bottom),
+            corner(left, top, u_left, v_top),
+            corner(left, top, u_left, v_top),
+            corner(right, bottom, u_right, v_bottom),
+            corner(right, top, u_right, v_top),
+        ]);
+    }
+
+    pub unsafe fn draw(&mut self, window_size: (i32, i32)) {
+        if self.vertices.is_empty() || !self.shader.is_compiled() {
+            self.vertices.clear();
+            return;
+        }
+        // Pixels to normalized device coordinates, y pointing up
+        let (width, height) = (window_size.0 as f32, window_size.1 as f32);
+        for vertex in self.vertices.iter_mut() {
+            vertex.position = [
+                2.0 * vertex.position[0] / width - 1.0,
+                1.0 - 2.0 * vertex.position[1] / height,
+            ];
+        }
+        self.vao.vbo.set_data(&self.vertices, gl::DYNAMIC_DRAW);
+        self.vertices.clear();
+
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::Viewport(0, 0, window_size.0, window_size.1);
+
+        self.shader.apply();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.font_texture.id);
+        self.shader.set_sampler("font_texture", 0);
+        self.vao.bind();
+        gl::DrawArrays(
+            gl::TRIANGLES,
+            0,
+            self.vao.vbo.nbr_of_vertices.try_into().unwrap(),
+        );
+        self.vao.unbind();
+        self.shader.stop();
+
+        gl::Disable(gl::BLEND);
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use gl::types::{GLsync, GLuint};
+
+use super::render_graph::RenderGraph;
+
+// Bytes of one RGBA32F pixel
+const PIXEL_SIZE: usize = 16;
+
+// Pixels of the render targets copied to a pixel buffer and read once the GPU is done with
+// them, so the CPU never waits for the frame to be rendered. Values are those of a previous
+// frame, usually the last one.
+pub struct PixelReadback {
+    buffer_id: GLuint,
+    // Signaled once the copy of the pending request is done
+    fence: Option<GLsync>,
+    // Targets of the pending request, in the order of the buffer
+    pending: Vec<String>,
+    values: HashMap<String, [f32; 4]>,
+}
+
+impl PixelReadback {
+    pub unsafe fn new() -> Self {
+        let mut buffer_id: GLuint = 0;
+        gl::GenBuffers(1, &mut buffer_id);
+        Self {
+            buffer_id,
+            fence: None,
+            pending: Vec::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    // Copy `pixel` of each target, unless the previous copy is not read yet
+    pub unsafe fn request(&mut self, render_graph: &RenderGraph, targets: &[(&str, (i32, i32))]) {
+        if self.fence.is_some() || targets.is_empty() {
+            return;
+        }
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.buffer_id);
+        gl::BufferData(
+            gl::PIXEL_PACK_BUFFER,
+            (PIXEL_SIZE * targets.len()) as isize,
+            std::ptr::null(),
+            gl::STREAM_READ,
+        );
+        self.pending.clear();
+        for (name, pixel) in targets {
+            if render_graph.read_target_pixel(name, *pixel, PIXEL_SIZE * self.pending.len()) {
+                self.pending.push(name.to_string());
+            }
+        }
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        if !self.pending.is_empty() {
+            self.fence = Some(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+        }
+    }
+
+    // Read the values of the pending request if the GPU has copied them
+    pub unsafe fn update(&mut self) {
+        let Some(fence) = self.fence else {
+            return;
+        };
+        let status = gl::ClientWaitSync(fence, 0, 0);
+        if status != gl::ALREADY_SIGNALED && status != gl::CONDITION_SATISFIED {
+            return;
+        }
+        gl::DeleteSync(fence);
+        self.fence = None;
+
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.buffer_id);
+        let size = PIXEL_SIZE * self.pending.len();
+        let data = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, size as isize, gl::MAP_READ_BIT);
+        if !data.is_null() {
+            let pixels = std::slice::from_raw_parts(data as *const [f32; 4], self.pending.len());
+            for (name, value) in self.pending.iter().zip(pixels) {
+                self.values.insert(name.clone(), *value);
+            }
+            gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+        }
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+    }
+
+    // Last value read from a target
+    pub fn value(&self, name: &str) -> Option<[f32; 4]> {
+        self.values.get(name).copied()
+    }
+
+    // Forget the values of a target no longer read, e.g. when the cursor leaves the window
+    pub fn forget(&mut self, name: &str) {
+        self.values.remove(name);
+    }
+}
+
+impl Drop for PixelReadback {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(fence) = self.fence {
+                gl::DeleteSync(fence);
+            }
+            gl::DeleteBuffers(1, &self.buffer_id);
+        }
+    }
+}
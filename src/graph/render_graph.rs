@@ -0,0 +1,469 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use gl::types::{GLenum, GLuint};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::io::assets::read_asset;
+
+use super::{
+    fbo::FboParameter,
+    gpu_timer::GpuTimer,
+    mesh::Mesh,
+    texture::{Texture2D, TextureParameter},
+};
+
+#[derive(Debug, Error)]
+pub enum RenderGraphError {
+    #[error("Can't read render graph: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Can't parse render graph: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Pass {0} reads texture {1} before it is rendered")]
+    TextureNotRendered(String, String),
+    #[error("History texture {0} copies unknown target {1}")]
+    UnknownHistorySource(String, String),
+    #[error("Pass {0} reads unknown texture {1}")]
+    UnknownTexture(String, String),
+}
+
+// Description of the passes, read from a json file
+#[derive(Deserialize)]
+struct RenderGraphDescription {
+    // Textures owned outside of the graph, set with `set_external_texture`
+    #[serde(default)]
+    external: Vec<String>,
+    #[serde(default)]
+    history: Vec<HistoryDescription>,
+    passes: Vec<PassDescription>,
+}
+
+// Copy of a target made at the beginning of the frame, before it is rendered again
+#[derive(Deserialize)]
+struct HistoryDescription {
+    name: String,
+    source: String,
+}
+
+#[derive(Deserialize)]
+struct PassDescription {
+    name: String,
+    shader: String,
+    // Textures rendered by the pass, output `i` of the shader is written in target `i`.
+    // The pass draws on the window if there is none.
+    #[serde(default)]
+    targets: Vec<String>,
+    // Depth written by the shader with gl_FragDepth
+    #[serde(default)]
+    depth: Option<String>,
+    #[serde(default)]
+    inputs: Vec<PassInput>,
+    #[serde(default)]
+    uniforms: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PassInput {
+    uniform: String,
+    texture: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum UniformValue {
+    Float(f32),
+    Int(i32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Mat4([f32; 16]),
+}
+
+enum TextureSource {
+    // Pass and index of the color attachment
+    PassTarget(usize, usize),
+    PassDepth(usize),
+    History(usize),
+    // Texture owned outside of the graph (volume, bricks...), set every frame
+    External(String),
+}
+
+struct PassTexture {
+    uniform: String,
+    source: TextureSource,
+}
+
+struct Pass {
+    name: String,
+    mesh: Mesh,
+    targets: Vec<String>,
+    depth: Option<String>,
+    inputs: Vec<PassTexture>,
+    uniforms: Vec<String>,
+    timer: GpuTimer,
+}
+
+struct HistoryTexture {
+    // Pass and index of the copied color attachment
+    source: (usize, usize),
+    texture: Texture2D,
+}
+
+// Passes drawn one after the other. A pass declares the textures it reads (targets of previous
+// passes, history copies or external textures) and the uniforms it needs.
+// Passes with a target run at the render resolution, the last one draws on the window.
+pub struct RenderGraph {
+    passes: Vec<Pass>,
+    history: Vec<HistoryTexture>,
+    external_textures: HashMap<String, (GLenum, GLuint)>,
+    uniforms: HashMap<String, UniformValue>,
+    render_size: (i32, i32),
+    window_size: (i32, i32),
+    shader_errors: Vec<String>,
+    // Uniforms of a pass (pass name, uniform name) already reported as never set, to warn
+    // only once
+    unset_uniforms: RefCell<HashSet<(String, String)>>,
+}
+
+impl RenderGraph {
+    pub unsafe fn load(
+        path: &str,
+        render_size: (i32, i32),
+        window_size: (i32, i32),
+    ) -> Result<Self, RenderGraphError> {
+        let description: RenderGraphDescription = serde_json::from_str(&read_asset(path)?)?;
+
+        // Targets rendered by each pass, depth included
+        let targets: Vec<Vec<String>> = description
+            .passes
+            .iter()
+            .map(|pass| {
+                pass.targets
+                    .iter()
+                    .chain(pass.depth.iter())
+                    .cloned()
+                    .collect()
+            })
+            .collect();
+
+        let mut passes: Vec<Pass> = Vec::new();
+        for pass_description in description.passes {
+            let mut inputs = Vec::new();
+            for input in pass_description.inputs {
+                let source = match Self::find_texture(
+                    &passes,
+                    &description.history,
+                    &description.external,
+                    &input.texture,
+                ) {
+                    Some(source) => source,
+                    // A target rendered by a next pass would be read before being drawn
+                    None if targets[passes.len()..]
+                        .iter()
+                        .any(|pass_targets| pass_targets.contains(&input.texture)) =>
+                    {
+                        return Err(RenderGraphError::TextureNotRendered(
+                            pass_description.name,
+                            input.texture,
+                        ))
+                    }
+                    None => {
+                        return Err(RenderGraphError::UnknownTexture(
+                            pass_description.name,
+                            input.texture,
+                        ))
+                    }
+                };
+                inputs.push(PassTexture {
+                    uniform: input.uniform,
+                    source,
+                });
+            }
+
+            let mesh = if pass_description.targets.is_empty() {
+                Mesh::new(pass_description.shader, false, Option::None)
+            } else {
+                Mesh::new(
+                    pass_description.shader,
+                    true,
+                    Some(FboParameter {
+                        texture_parameter: TextureParameter::new_float_parameter(render_size),
+                        color_attachments: pass_description.targets.len(),
+                        with_depth: pass_description.depth.is_some(),
+                    }),
+                )
+            };
+            passes.push(Pass {
+                name: pass_description.name,
+                mesh,
+                targets: pass_description.targets,
+                depth: pass_description.depth,
+                inputs,
+                uniforms: pass_description.uniforms,
+                timer: GpuTimer::new(),
+            });
+        }
+
+        let mut history = Vec::new();
+        for history_description in description.history {
+            let source = match Self::find_texture(&passes, &[], &[], &history_description.source) {
+                Some(TextureSource::PassTarget(pass_index, attachment)) => (pass_index, attachment),
+                _ => {
+                    return Err(RenderGraphError::UnknownHistorySource(
+                        history_description.name,
+                        history_description.source,
+                    ))
+                }
+            };
+            history.push(HistoryTexture {
+                source,
+                texture: Texture2D::new(TextureParameter::new_float_parameter(render_size)),
+            });
+        }
+
+        let mut render_graph = Self {
+            passes,
+            history,
+            external_textures: HashMap::new(),
+            uniforms: HashMap::new(),
+            render_size,
+            window_size,
+            shader_errors: Vec::new(),
+            unset_uniforms: RefCell::new(HashSet::new()),
+        };
+        render_graph.recompile();
+        Ok(render_graph)
+    }
+
+    // Textures are looked up in the targets of the previous passes, then in the history and in
+    // the declared external textures
+    fn find_texture(
+        previous_passes: &[Pass],
+        history: &[HistoryDescription],
+        external: &[String],
+        name: &str,
+    ) -> Option<TextureSource> {
+        for (pass_index, pass) in previous_passes.iter().enumerate() {
+            if let Some(attachment) = pass.targets.iter().position(|target| target == name) {
+                return Some(TextureSource::PassTarget(pass_index, attachment));
+            }
+            if pass.depth.as_deref() == Some(name) {
+                return Some(TextureSource::PassDepth(pass_index));
+            }
+        }
+        if let Some(index) = history.iter().position(|history| history.name == name) {
+            return Some(TextureSource::History(index));
+        }
+        external
+            .iter()
+            .any(|external| external == name)
+            .then(|| TextureSource::External(name.to_string()))
+    }
+
+    // Passes whose shader fails to compile keep their previous program (or are skipped if
+    // they never compiled), errors are kept to be shown on screen
+    pub unsafe fn recompile(&mut self) {
+        self.shader_errors.clear();
+        for pass in self.passes.iter_mut() {
+            if let Err(error) = pass.mesh.compile() {
+                let message = format!("Shader of pass {}: {}", pass.name, error);
+                println!("{}", message);
+                self.shader_errors.push(message);
+            }
+        }
+    }
+
+    pub fn shader_errors(&self) -> &[String] {
+        &self.shader_errors
+    }
+
+    // Recreate the targets and history textures, previous content is lost
+    pub unsafe fn resize(&mut self, render_size: (i32, i32), window_size: (i32, i32)) {
+        self.render_size = render_size;
+        self.window_size = window_size;
+        for pass in self.passes.iter_mut() {
+            pass.mesh.shader.resize_fbo(render_size);
+        }
+        for history in self.history.iter_mut() {
+            history.texture = Texture2D::new(TextureParameter::new_float_parameter(render_size));
+        }
+    }
+
+    pub fn set_uniform(&mut self, name: &str, value: UniformValue) {
+        self.uniforms.insert(name.to_string(), value);
+    }
+
+    pub fn set_external_texture(&mut self, name: &str, target: GLenum, texture_id: GLuint) {
+        self.external_textures
+            .insert(name.to_string(), (target, texture_id));
+    }
+
+    pub unsafe fn execute(&mut self) {
+        for history in self.history.iter() {
+            Texture2D::copy(
+                self.target_texture_id(history.source.0, history.source.1),
+                history.texture.id,
+                self.render_size,
+            );
+        }
+
+        for index in 0..self.passes.len() {
+            // A pass whose shader never compiled has no program to draw with
+            if !self.passes[index].mesh.shader.is_compiled() {
+                continue;
+            }
+            self.passes[index].timer.begin();
+            let pass = &self.passes[index];
+            pass.mesh.shader.apply();
+            self.send_uniforms(pass);
+            self.bind_inputs(pass);
+            pass.mesh.shader.stop();
+
+            let viewport_size = if pass.targets.is_empty() {
+                self.window_size
+            } else {
+                self.render_size
+            };
+            gl::Viewport(0, 0, viewport_size.0, viewport_size.1);
+
+            // Depth is only written when the depth test is enabled
+            if pass.depth.is_some() {
+                gl::Enable(gl::DEPTH_TEST);
+                gl::DepthFunc(gl::ALWAYS);
+            }
+            pass.mesh.draw();
+            if pass.depth.is_some() {
+                gl::Disable(gl::DEPTH_TEST);
+            }
+            self.passes[index].timer.end();
+        }
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+
+    // GPU time of each pass in seconds, measured a few frames ago
+    pub fn pass_times(&self) -> Vec<(String, f32)> {
+        self.passes
+            .iter()
+            .map(|pass| (pass.name.clone(), pass.timer.elapsed_time()))
+            .collect()
+    }
+
+    pub fn gpu_time(&self) -> f32 {
+        self.passes
+            .iter()
+            .map(|pass| pass.timer.elapsed_time())
+            .sum()
+    }
+
+    // Start reading one pixel of a pass target into the pixel pack buffer bound by the caller,
+    // at `offset` bytes. Returns false if no pass has this target.
+    pub unsafe fn read_target_pixel(&self, name: &str, pixel: (i32, i32), offset: usize) -> bool {
+        let (pass_index, attachment) = match Self::find_texture(&self.passes, &[], &[], name) {
+            Some(TextureSource::PassTarget(pass_index, attachment)) => (pass_index, attachment),
+            _ => return false,
+        };
+        let Some(fbo) = self.passes[pass_index].mesh.shader.fbo.as_ref() else {
+            return false;
+        };
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo.fbo_id);
+        gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment as u32);
+        gl::ReadPixels(
+            pixel.0,
+            pixel.1,
+            1,
+            1,
+            gl::RGBA,
+            gl::FLOAT,
+            offset as *mut std::ffi::c_void,
+        );
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        true
+    }
+
+    // Memory used by the targets and history textures, in bytes
+    pub fn texture_memory(&self) -> usize {
+        let pixel_count = (self.render_size.0 * self.render_size.1) as usize;
+        let color_textures = self.history.len()
+            + self
+                .passes
+                .iter()
+                .map(|pass| pass.targets.len())
+                .sum::<usize>();
+        let depth_textures = self
+            .passes
+            .iter()
+            .filter(|pass| pass.depth.is_some())
+            .count();
+        // RGBA32F color and 32 bits depth
+        pixel_count * (16 * color_textures + 4 * depth_textures)
+    }
+
+    // The shader of the pass must be in use
+    unsafe fn send_uniforms(&self, pass: &Pass) {
+        for name in pass.uniforms.iter() {
+            match self.uniforms.get(name) {
+                Some(UniformValue::Float(value)) => pass.mesh.shader.set_f32(name, *value),
+                Some(UniformValue::Int(value)) => pass.mesh.shader.set_i32(name, *value),
+                Some(UniformValue::Vec2(value)) => pass.mesh.shader.set_vec2(name, *value),
+                Some(UniformValue::Vec3(value)) => pass.mesh.shader.set_vec3(name, *value),
+                Some(UniformValue::Mat4(value)) => pass.mesh.shader.set_mat4(name, *value),
+                None => {
+                    if self
+                        .unset_uniforms
+                        .borrow_mut()
+                        .insert((pass.name.clone(), name.clone()))
+                    {
+                        println!("Uniform {} of pass {} is never set", name, pass.name);
+                    }
+                }
+            }
+        }
+    }
+
+    // Input textures are bound on consecutive texture units in declaration order
+    unsafe fn bind_inputs(&self, pass: &Pass) {
+        for (unit, input) in pass.inputs.iter().enumerate() {
+            let (target, texture_id) = match &input.source {
+                TextureSource::PassTarget(pass_index, attachment) => (
+                    gl::TEXTURE_2D,
+                    self.target_texture_id(*pass_index, *attachment),
+                ),
+                TextureSource::PassDepth(pass_index) => {
+                    (gl::TEXTURE_2D, self.depth_texture_id(*pass_index))
+                }
+                TextureSource::History(index) => (gl::TEXTURE_2D, self.history[*index].texture.id),
+                TextureSource::External(name) => self
+                    .external_textures
+                    .get(name)
+                    .copied()
+                    .unwrap_or((gl::TEXTURE_2D, 0)),
+            };
+            gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+            gl::BindTexture(target, texture_id);
+            pass.mesh.shader.set_sampler(&input.uniform, unit as u32);
+        }
+    }
+
+    fn target_texture_id(&self, pass_index: usize, attachment: usize) -> GLuint {
+        self.passes[pass_index]
+            .mesh
+            .shader
+            .fbo
+            .as_ref()
+            .expect("Pass without target can't be read")
+            .textures[attachment]
+            .id
+    }
+
+    fn depth_texture_id(&self, pass_index: usize) -> GLuint {
+        self.passes[pass_index]
+            .mesh
+            .shader
+            .fbo
+            .as_ref()
+            .and_then(|fbo| fbo.depth_texture.as_ref())
+            .expect("Pass without depth can't be read")
+            .id
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+const RENDER_SCALE_STEP: f32 = 0.125;
+// GPU time per frame the automatic mode tries to hold (60 frames per second)
+const TARGET_FRAME_TIME: f32 = 1.0 / 60.0;
+// Time between two automatic adjustments, so the render targets are not recreated every frame
+const ADJUSTMENT_INTERVAL: Duration = Duration::from_millis(500);
+
+// Ratio between the resolution of the raymarching and lighting passes and the window size
+pub struct RenderScale {
+    scale: f32,
+    automatic: bool,
+    smoothed_frame_time: f32,
+    last_adjustment: Instant,
+}
+
+impl RenderScale {
+    pub fn new() -> Self {
+        Self {
+            scale: 1.0,
+            automatic: false,
+            smoothed_frame_time: TARGET_FRAME_TIME,
+            last_adjustment: Instant::now(),
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn is_automatic(&self) -> bool {
+        self.automatic
+    }
+
+    pub fn toggle_automatic(&mut self) {
+        self.automatic = !self.automatic;
+        self.smoothed_frame_time = TARGET_FRAME_TIME;
+        self.last_adjustment = Instant::now();
+    }
+
+    // Manual changes leave the automatic mode. Return true if the scale changed.
+    pub fn increase(&mut self) -> bool {
+        self.automatic = false;
+        self.set_scale(self.scale + RENDER_SCALE_STEP)
+    }
+
+    pub fn decrease(&mut self) -> bool {
+        self.automatic = false;
+        self.set_scale(self.scale - RENDER_SCALE_STEP)
+    }
+
+    fn set_scale(&mut self, scale: f32) -> bool {
+        let scale = scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        let changed = scale != self.scale;
+        self.scale = scale;
+        changed
+    }
+
+    // Size of the internal render targets for a window of `window_size` pixels
+    pub fn render_size(&self, window_size: (i32, i32)) -> (i32, i32) {
+        (
+            ((window_size.0 as f32 * self.scale).round() as i32).max(1),
+            ((window_size.1 as f32 * self.scale).round() as i32).max(1),
+        )
+    }
+
+    // Feed the GPU time of the last frame (in seconds) to the automatic mode.
+    // Return true if the scale changed and the render targets must be recreated.
+    pub fn record_frame_time(&mut self, frame_time: f32) -> bool {
+        if !self.automatic {
+            return false;
+        }
+        self.smoothed_frame_time = 0.9 * self.smoothed_frame_time + 0.1 * frame_time;
+        if self.last_adjustment.elapsed() < ADJUSTMENT_INTERVAL {
+            return false;
+        }
+        self.last_adjustment = Instant::now();
+
+        // The margin between both thresholds avoids oscillating between two scales
+        if self.smoothed_frame_time > 1.05 * TARGET_FRAME_TIME {
+            self.set_scale(self.scale - RENDER_SCALE_STEP)
+        } else if self.smoothed_frame_time < 0.7 * TARGET_FRAME_TIME {
+            self.set_scale(self.scale + RENDER_SCALE_STEP)
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::player::{camera::ProjectionSettings, player::MotionSettings};
+
+use super::{gui::GuiPanel, render_graph::UniformValue};
+
+const RENDER_MODES: [&str; 4] = ["shaded", "intensity", "lighting", "normals"];
+const COLORMAPS: [&str; 2] = ["grayscale", "hot"];
+const AXIS_NAMES: [&str; 3] = ["X", "Y", "Z"];
+
+// Settings of the passes edited in the GUI panel and sent as uniforms every frame
+#[derive(Clone, PartialEq)]
+pub struct RenderSettings {
+    // Index in RENDER_MODES
+    pub render_mode: usize,
+    // Intensities between center - width / 2 and center + width / 2 are stretched to the
+    // whole display range
+    pub window_center: f32,
+    pub window_width: f32,
+    // Index in COLORMAPS
+    pub colormap: usize,
+    // Voxels whose intensity is outside this range are transparent
+    pub visible_minimum: f32,
+    pub visible_maximum: f32,
+    pub light_offset: f32,
+    // Direction the sunlight comes from, in degrees: angle around the vertical axis and
+    // height above the horizon
+    pub sun_azimuth: f32,
+    pub sun_elevation: f32,
+    pub bilateral_filter: bool,
+    pub kernel_size: f32,
+    pub sigma_space: f32,
+    pub sigma_range: f32,
+    pub light_diminution: f32,
+    // Part of the volume kept on each axis, from 0 to 1
+    pub clip_minimum: [f32; 3],
+    pub clip_maximum: [f32; 3],
+    pub camera_motion: MotionSettings,
+    pub camera_projection: ProjectionSettings,
+}
+
+impl RenderSettings {
+    pub fn new(camera_projection: ProjectionSettings) -> Self {
+        Self {
+            render_mode: 0,
+            window_center: 0.5,
+            window_width: 1.0,
+            colormap: 0,
+            visible_minimum: 0.0,
+            visible_maximum: 1.0,
+            light_offset: 0.15,
+            sun_azimuth: 0.0,
+            sun_elevation: 60.0,
+            bilateral_filter: false,
+            kernel_size: 5.0,
+            sigma_space: 0.1,
+            sigma_range: 0.2,
+            light_diminution: 15.0,
+            clip_minimum: [0.0; 3],
+            clip_maximum: [1.0; 3],
+            camera_motion: MotionSettings::new(),
+            camera_projection,
+        }
+    }
+
+    pub fn uniforms(&self) -> Vec<(&'static str, UniformValue)> {
+        vec![
+            ("render_mode", UniformValue::Int(self.render_mode as i32)),
+            (
+                "window_level",
+                UniformValue::Vec2([self.window_center, self.window_width]),
+            ),
+            ("colormap", UniformValue::Int(self.colormap as i32)),
+            (
+                "visible_range",
+                UniformValue::Vec2([self.visible_minimum, self.visible_maximum]),
+            ),
+            ("light_offset", UniformValue::Float(self.light_offset)),
+            ("sun_direction", UniformValue::Vec3(self.sun_direction())),
+            (
+                "bilateral_filter",
+                UniformValue::Int(self.bilateral_filter as i32),
+            ),
+            ("kernel_size", UniformValue::Float(self.kernel_size.round())),
+            ("sigma_space", UniformValue::Float(self.sigma_space)),
+            ("sigma_range", UniformValue::Float(self.sigma_range)),
+            (
+                "light_diminution",
+                UniformValue::Float(self.light_diminution),
+            ),
+            ("clip_minimum", UniformValue::Vec3(self.clip_minimum)),
+            ("clip_maximum", UniformValue::Vec3(self.clip_maximum)),
+        ]
+    }
+
+    // Direction the sunlight travels in, downwards along -Y when the sun is at the zenith
+    fn sun_direction(&self) -> [f32; 3] {
+        let (azimuth, elevation) = (
+            self.sun_azimuth.to_radians(),
+            self.sun_elevation.to_radians(),
+        );
+        [
+            -elevation.cos() * azimuth.cos(),
+            -elevation.sin(),
+            -elevation.cos() * azimuth.sin(),
+        ]
+    }
+
+    // Widgets of the settings panel, return true if a setting changed. The reset button
+    // restores `defaults`, the settings given at startup.
+    pub fn edit(&mut self, panel: &mut GuiPanel, defaults: &RenderSettings) -> bool {
+        let previous_settings = self.clone();
+
+        panel.title("Render settings");
+        panel.selector("Render mode", &mut self.render_mode, &RENDER_MODES);
+        panel.slider("Window center", &mut self.window_center, 0.0..=1.0);
+        panel.slider("Window width", &mut self.window_width, 0.01..=2.0);
+        panel.selector("Colormap", &mut self.colormap, &COLORMAPS);
+        panel.slider("Visible min", &mut self.visible_minimum, 0.0..=1.0);
+        panel.slider("Visible max", &mut self.visible_maximum, 0.0..=1.0);
+        panel.slider("Light offset", &mut self.light_offset, 0.0..=1.0);
+        panel.slider("Sun azimuth", &mut self.sun_azimuth, 0.0..=360.0);
+        panel.slider("Sun elevation", &mut self.sun_elevation, 5.0..=90.0);
+
+        panel.title("Filter");
+        panel.checkbox("Bilateral", &mut self.bilateral_filter);
+        panel.slider("Kernel size", &mut self.kernel_size, 1.0..=8.0);
+        panel.slider("Sigma space", &mut self.sigma_space, 0.01..=1.0);
+        panel.slider("Sigma range", &mut self.sigma_range, 0.01..=1.0);
+        panel.slider("Light dimin.", &mut self.light_diminution, 1.0..=50.0);
+
+        panel.title("Clip planes");
+        for (axis, axis_name) in AXIS_NAMES.iter().enumerate() {
+            panel.slider(
+                &format!("{} min", axis_name),
+                &mut self.clip_minimum[axis],
+                0.0..=1.0,
+            );
+            panel.slider(
+                &format!("{} max", axis_name),
+                &mut self.clip_maximum[axis],
+                0.0..=1.0,
+            );
+        }
+
+        panel.title("Camera");
+        panel.slider("Speed", &mut self.camera_motion.speed, 0.01..=1.0);
+        panel.slider(
+            "Acceleration",
+            &mut self.camera_motion.acceleration,
+            1.0..=30.0,
+        );
+        panel.slider("Damping", &mut self.camera_motion.damping, 1.0..=30.0);
+        panel.slider(
+            "Mouse smooth.",
+            &mut self.camera_motion.mouse_smoothing,
+            0.0..=0.2,
+        );
+        panel.slider(
+            "Field of view",
+            &mut self.camera_projection.field_of_view,
+            10.0..=120.0,
+        );
+        panel.slider(
+            "Near plane",
+            &mut self.camera_projection.near_plane,
+            0.0001..=0.1,
+        );
+        panel.slider(
+            "Far plane",
+            &mut self.camera_projection.far_plane,
+            1.0..=100.0,
+        );
+        panel.slider(
+            "Max ray dist.",
+            &mut self.camera_projection.max_ray_distance,
+            0.1..=100.0,
+        );
+        if panel.button("Reset") {
+            *self = defaults.clone();
+        }
+
+        *self != previous_settings
+    }
+}
@@ -1,8 +1,12 @@
-use crate::graph::fbo::FBO;
+use crate::{
+    graph::fbo::{FboParameter, FBO},
+    world::brick::{BRICK_SIZE, MACRO_CELL_SIZE},
+};
 
 use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
     ffi::{CString, NulError},
-    fs::read_to_string,
     ptr,
     string::FromUtf8Error,
 };
@@ -10,7 +14,9 @@ use std::{
 use gl::types::{GLenum, GLint, GLuint};
 use thiserror::Error;
 
-use super::texture::TextureParameter;
+use super::shader_preprocessor::preprocess;
+
+const SHADER_DIRECTORY: &str = "shaders";
 
 #[derive(Debug, Error)]
 pub enum ShaderError {
@@ -18,79 +24,107 @@ pub enum ShaderError {
     CompilationError(String),
     #[error("Error while linking shaders: {0}")]
     LinkingError(String),
+    #[error("Error while including shader file: {0}")]
+    IncludeError(String),
     #[error{"{0}"}]
     Utf8Error(#[from] FromUtf8Error),
     #[error{"{0}"}]
     NulError(#[from] NulError),
+    #[error{"{0}"}]
+    Utf8NameError(#[from] std::str::Utf8Error),
 }
 
 pub struct Shader {
-    pub id: GLuint,
+    // None until the shaders compile once, a failed reload keeps the previous program
+    id: Option<GLuint>,
     pub fbo: Option<FBO>,
     pub file_name: CString,
+    // Locations of the active uniforms, queried once the program is linked
+    uniform_locations: HashMap<String, GLint>,
+    // Uniforms already reported as missing, to warn only once
+    missing_uniforms: RefCell<HashSet<String>>,
 }
 
 impl Shader {
-    pub unsafe fn new_with_fbo(file_name: CString, texture_parameter: TextureParameter) -> Self {
-        let fbo = Some(FBO::new(texture_parameter));
+    pub unsafe fn new_with_fbo(file_name: CString, fbo_parameter: FboParameter) -> Self {
+        let fbo = Some(FBO::new(fbo_parameter));
 
         Self {
-            id: 0,
+            id: None,
             fbo: fbo,
             file_name: file_name,
+            uniform_locations: HashMap::new(),
+            missing_uniforms: RefCell::new(HashSet::new()),
         }
     }
 
     pub unsafe fn new_without_fbo(file_name: CString) -> Self {
         Self {
-            id: 0,
+            id: None,
             fbo: Option::None,
             file_name: file_name,
+            uniform_locations: HashMap::new(),
+            missing_uniforms: RefCell::new(HashSet::new()),
         }
     }
 
-    pub unsafe fn compile(&mut self) {
-        let vertex_shader_id_result = Self::create_shader_from_str(
-            &read_to_string(format!(
-                "assets/shaders/{}_vs.glsl",
-                self.file_name.to_str().unwrap().to_owned()
-            ))
-            .unwrap(),
-            gl::VERTEX_SHADER,
-        );
+    // Constants of the application shared with every shader
+    fn defines() -> Vec<(&'static str, String)> {
+        vec![
+            ("BRICK_SIZE", format!("{:.1}", BRICK_SIZE as f32)),
+            ("MACRO_CELL_SIZE", format!("{}", MACRO_CELL_SIZE)),
+        ]
+    }
 
-        let fragment_shader_id_result = Self::create_shader_from_str(
-            &read_to_string(format!(
-                "assets/shaders/{}_fs.glsl",
-                self.file_name.to_str().unwrap().to_owned()
-            ))
-            .unwrap(),
+    // Compile and link the shaders of the program. On failure the previous program is kept,
+    // so a shader can be fixed and reloaded without restarting.
+    pub unsafe fn compile(&mut self) -> Result<(), ShaderError> {
+        let defines = Self::defines();
+        let shader_name = self.file_name.to_str()?.to_owned();
+        let vertex_shader_id = Self::compile_file(
+            &format!("{}_vs.glsl", shader_name),
+            gl::VERTEX_SHADER,
+            &defines,
+        )?;
+        let fragment_shader_id = match Self::compile_file(
+            &format!("{}_fs.glsl", shader_name),
             gl::FRAGMENT_SHADER,
-        );
-
-        if self.id == 0 {
-            if vertex_shader_id_result.is_err() {
-                panic!("{}", vertex_shader_id_result.unwrap());
+            &defines,
+        ) {
+            Ok(fragment_shader_id) => fragment_shader_id,
+            Err(error) => {
+                gl::DeleteShader(vertex_shader_id);
+                return Err(error);
             }
+        };
 
-            if fragment_shader_id_result.is_err() {
-                panic!("{}", fragment_shader_id_result.unwrap());
-            }
-        }
+        let program_id_result = Self::create_program(vertex_shader_id, fragment_shader_id);
+        // Shader objects are released with the program they are attached to
+        gl::DeleteShader(vertex_shader_id);
+        gl::DeleteShader(fragment_shader_id);
+        let program_id = program_id_result?;
 
-        if !vertex_shader_id_result.is_err() && !fragment_shader_id_result.is_err() {
-            let program_id = Self::create_program(
-                vertex_shader_id_result.unwrap(),
-                fragment_shader_id_result.unwrap(),
-            )
-            .expect(&format!(
-                "Can not create shader {}",
-                self.file_name.to_str().unwrap().to_owned()
-            ));
-            self.id = program_id;
-        } else {
-            println!("Error while reloading shader {:?}", self.file_name);
+        if let Some(previous_program_id) = self.id.replace(program_id) {
+            gl::DeleteProgram(previous_program_id);
         }
+        self.uniform_locations = Self::query_uniform_locations(program_id);
+        self.missing_uniforms.borrow_mut().clear();
+        Ok(())
+    }
+
+    // Line numbers of the compiler log are given in the files before preprocessing
+    unsafe fn compile_file(
+        file_name: &str,
+        shader_type: GLenum,
+        defines: &[(&str, String)],
+    ) -> Result<GLuint, ShaderError> {
+        let source = preprocess(file_name, SHADER_DIRECTORY, defines)?;
+        Self::create_shader_from_str(&source.code, shader_type).map_err(|error| match error {
+            ShaderError::CompilationError(log) => {
+                ShaderError::CompilationError(format!("{}\n{}", file_name, source.map_log(&log)))
+            }
+            error => error,
+        })
     }
 
     unsafe fn create_shader_from_str(
@@ -120,6 +154,7 @@ impl Shader {
             );
 
             error_log.set_len(error_log_size as usize);
+            gl::DeleteShader(current_shader_id);
             let log = String::from_utf8(error_log)?;
             return Err(ShaderError::CompilationError(log));
         }
@@ -153,13 +188,108 @@ impl Shader {
             );
 
             error_log.set_len(error_log_size as usize);
+            gl::DeleteProgram(program_id);
             let log = String::from_utf8(error_log)?;
             Err(ShaderError::LinkingError(log))
         }
     }
 
+    // Active uniforms of a linked program. Uniforms not used by the shader code are removed
+    // by the compiler and are not listed.
+    unsafe fn query_uniform_locations(program_id: GLuint) -> HashMap<String, GLint> {
+        let mut uniform_count: GLint = 0;
+        gl::GetProgramiv(program_id, gl::ACTIVE_UNIFORMS, &mut uniform_count);
+        let mut max_name_length: GLint = 0;
+        gl::GetProgramiv(
+            program_id,
+            gl::ACTIVE_UNIFORM_MAX_LENGTH,
+            &mut max_name_length,
+        );
+
+        let mut uniform_locations = HashMap::new();
+        for index in 0..uniform_count {
+            let mut name: Vec<u8> = vec![0; max_name_length.max(1) as usize];
+            let mut name_length: GLint = 0;
+            let mut size: GLint = 0;
+            let mut uniform_type: GLenum = 0;
+            gl::GetActiveUniform(
+                program_id,
+                index as GLuint,
+                max_name_length,
+                &mut name_length,
+                &mut size,
+                &mut uniform_type,
+                name.as_mut_ptr() as *mut _,
+            );
+            name.truncate(name_length as usize);
+            let location = gl::GetUniformLocation(program_id, name.as_ptr() as *const _);
+
+            // Arrays are listed as `name[0]`
+            let name = String::from_utf8_lossy(&name)
+                .trim_end_matches("[0]")
+                .to_string();
+            uniform_locations.insert(name, location);
+        }
+        uniform_locations
+    }
+
+    fn uniform_location(&self, name: &str) -> Option<GLint> {
+        let location = self.uniform_locations.get(name).copied();
+        if location.is_none() && self.missing_uniforms.borrow_mut().insert(name.to_string()) {
+            println!(
+                "Uniform {} does not exist in shader {:?}",
+                name, self.file_name
+            );
+        }
+        location
+    }
+
+    // Uniforms are set on the program in use: call them between apply and stop
+    pub unsafe fn set_f32(&self, name: &str, value: f32) {
+        if let Some(location) = self.uniform_location(name) {
+            gl::Uniform1f(location, value);
+        }
+    }
+
+    pub unsafe fn set_i32(&self, name: &str, value: i32) {
+        if let Some(location) = self.uniform_location(name) {
+            gl::Uniform1i(location, value);
+        }
+    }
+
+    pub unsafe fn set_vec2(&self, name: &str, value: [f32; 2]) {
+        if let Some(location) = self.uniform_location(name) {
+            gl::Uniform2fv(location, 1, value.as_ptr());
+        }
+    }
+
+    pub unsafe fn set_vec3(&self, name: &str, value: [f32; 3]) {
+        if let Some(location) = self.uniform_location(name) {
+            gl::Uniform3fv(location, 1, value.as_ptr());
+        }
+    }
+
+    pub unsafe fn set_mat4(&self, name: &str, value: [f32; 16]) {
+        if let Some(location) = self.uniform_location(name) {
+            gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr());
+        }
+    }
+
+    // Sampler reading the texture bound on `texture_unit`
+    pub unsafe fn set_sampler(&self, name: &str, texture_unit: u32) {
+        self.set_i32(name, texture_unit as i32);
+    }
+
+    // False if the shaders never compiled, the shader can't be used then
+    pub fn is_compiled(&self) -> bool {
+        self.id.is_some()
+    }
+
     pub unsafe fn apply(&self) {
-        gl::UseProgram(self.id);
+        let Some(id) = self.id else {
+            return;
+        };
+        gl::UseProgram(id);
         if self.fbo.is_some() {
             self.fbo.as_ref().unwrap().bind();
         }
@@ -172,16 +302,25 @@ impl Shader {
         gl::UseProgram(0);
     }
 
+    pub unsafe fn resize_fbo(&mut self, screen_size: (i32, i32)) {
+        if let Some(fbo) = self.fbo.as_mut() {
+            fbo.resize(screen_size);
+        }
+    }
+
+    // The shader must be compiled
     pub unsafe fn get_attrib_location(&self, attrib: &str) -> Result<GLuint, NulError> {
         let attrib = CString::new(attrib)?;
-        Ok(gl::GetAttribLocation(self.id, attrib.as_ptr()) as GLuint)
+        Ok(gl::GetAttribLocation(self.id.unwrap_or(0), attrib.as_ptr()) as GLuint)
     }
 }
 
 impl Drop for Shader {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteProgram(self.id);
+        if let Some(id) = self.id {
+            unsafe {
+                gl::DeleteProgram(id);
+            }
         }
     }
 }
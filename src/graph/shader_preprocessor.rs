@@ -0,0 +1,199 @@
+use crate::io::assets::read_asset;
+
+use super::shader::ShaderError;
+
+// Source ready to be compiled, `#line` directives refer to `files` by index
+pub struct PreprocessedSource {
+    pub code: String,
+    pub files: Vec<String>,
+}
+
+impl PreprocessedSource {
+    // Replace the source string numbers of a compiler log by the file names, e.g. Mesa's
+    // `1:12(5): error` becomes `include/volume.glsl:12(5): error`
+    pub fn map_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|line| self.map_log_line(line))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn map_log_line(&self, line: &str) -> String {
+        let (prefix, message) = match line.strip_prefix("ERROR: ") {
+            Some(message) => ("ERROR: ", message),
+            None => ("", line),
+        };
+        let index_length = message.chars().take_while(char::is_ascii_digit).count();
+        let location = &message[index_length..];
+        if index_length == 0 || !(location.starts_with(':') || location.starts_with('(')) {
+            return line.to_string();
+        }
+        match message[..index_length]
+            .parse::<usize>()
+            .ok()
+            .and_then(|file_index| self.files.get(file_index))
+        {
+            Some(file) => format!("{}{}{}", prefix, file, location),
+            None => line.to_string(),
+        }
+    }
+}
+
+// Resolve `#include "file"` directives (relative to the asset `directory`) and insert `#define NAME value`
+// after the `#version` line. Each file is included once, `#line` directives keep the line
+// numbers of the compiler log relative to the original files.
+pub fn preprocess(
+    file_name: &str,
+    directory: &str,
+    defines: &[(&str, String)],
+) -> Result<PreprocessedSource, ShaderError> {
+    preprocess_with(file_name, defines, &|file_name| {
+        read_asset(&format!("{}/{}", directory, file_name))
+    })
+}
+
+// Same as `preprocess`, the files being read by `read_file`
+fn preprocess_with(
+    file_name: &str,
+    defines: &[(&str, String)],
+    read_file: &dyn Fn(&str) -> std::io::Result<String>,
+) -> Result<PreprocessedSource, ShaderError> {
+    let mut preprocessed_source = PreprocessedSource {
+        code: String::new(),
+        files: Vec::new(),
+    };
+    include_file(file_name, defines, read_file, &mut preprocessed_source)?;
+    Ok(preprocessed_source)
+}
+
+fn include_file(
+    file_name: &str,
+    defines: &[(&str, String)],
+    read_file: &dyn Fn(&str) -> std::io::Result<String>,
+    preprocessed_source: &mut PreprocessedSource,
+) -> Result<(), ShaderError> {
+    let source = read_file(file_name)
+        .map_err(|error| ShaderError::IncludeError(format!("{}: {}", file_name, error)))?;
+    let file_index = preprocessed_source.files.len();
+    preprocessed_source.files.push(file_name.to_string());
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let trimmed_line = line.trim_start();
+
+        if trimmed_line.starts_with("#version") {
+            preprocessed_source.code.push_str(line);
+            preprocessed_source.code.push('\n');
+            for (name, value) in defines {
+                preprocessed_source
+                    .code
+                    .push_str(&format!("#define {} {}\n", name, value));
+            }
+            preprocessed_source.code.push_str(&format!(
+                "#line {} {}\n",
+                line_number + 1,
+                file_index
+            ));
+        } else if let Some(include) = trimmed_line.strip_prefix("#include") {
+            let included_file_name = include.trim().trim_matches('"');
+            if included_file_name.is_empty() {
+                return Err(ShaderError::IncludeError(format!(
+                    "{}:{}: missing file name after #include",
+                    file_name, line_number
+                )));
+            }
+            if !preprocessed_source
+                .files
+                .iter()
+                .any(|file| file == included_file_name)
+            {
+                preprocessed_source
+                    .code
+                    .push_str(&format!("#line 1 {}\n", preprocessed_source.files.len()));
+                include_file(included_file_name, defines, read_file, preprocessed_source)?;
+            }
+            preprocessed_source.code.push_str(&format!(
+                "#line {} {}\n",
+                line_number + 1,
+                file_index
+            ));
+        } else {
+            preprocessed_source.code.push_str(line);
+            preprocessed_source.code.push('\n');
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error, ErrorKind};
+
+    use super::*;
+
+    fn read_test_file(file_name: &str) -> std::io::Result<String> {
+        let source = match file_name {
+            "main_fs.glsl" => {
+                "#version 330\n#include \"a.glsl\"\n#include \"b.glsl\"\nvoid main() {}\n"
+            }
+            "a.glsl" => "// a\n#include \"b.glsl\"\nfloat a() { return b(); }\n",
+            "b.glsl" => "float b() { return SIZE; }\n",
+            _ => return Err(Error::new(ErrorKind::NotFound, "no such file")),
+        };
+        Ok(source.to_string())
+    }
+
+    #[test]
+    fn nested_includes_keep_the_line_numbers_of_each_file() {
+        let source = preprocess_with(
+            "main_fs.glsl",
+            &[("SIZE", "8.0".to_string())],
+            &read_test_file,
+        )
+        .unwrap();
+        assert_eq!(source.files, vec!["main_fs.glsl", "a.glsl", "b.glsl"]);
+        let expected = [
+            "#version 330",
+            "#define SIZE 8.0",
+            "#line 2 0",
+            "#line 1 1",
+            "// a",
+            "#line 1 2",
+            "float b() { return SIZE; }",
+            "#line 3 1",
+            "float a() { return b(); }",
+            "#line 3 0",
+            // b.glsl is already included
+            "#line 4 0",
+            "void main() {}",
+        ];
+        assert_eq!(source.code.lines().collect::<Vec<&str>>(), expected);
+    }
+
+    #[test]
+    fn log_lines_are_mapped_to_file_names() {
+        let source = preprocess_with("main_fs.glsl", &[], &read_test_file).unwrap();
+        let log = "0:4(1): error: syntax error\nERROR: 2:1: 'SIZE' : undeclared identifier\n1:3(20): warning\n7:1(1): error\nlinker message";
+        assert_eq!(
+            source.map_log(log),
+            "main_fs.glsl:4(1): error: syntax error\n\
+             ERROR: b.glsl:1: 'SIZE' : undeclared identifier\n\
+             a.glsl:3(20): warning\n\
+             7:1(1): error\n\
+             linker message"
+        );
+    }
+
+    #[test]
+    fn missing_includes_are_reported() {
+        let error = preprocess_with("a.glsl", &[], &|file_name| match file_name {
+            "a.glsl" => Ok("#include \"missing.glsl\"\n".to_string()),
+            _ => read_test_file(file_name),
+        })
+        .err()
+        .unwrap();
+        assert!(
+            matches!(error, ShaderError::IncludeError(message) if message.starts_with("missing.glsl"))
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+// Time between two reports of the statistics
+const REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+// CPU timings of the main loop, averaged and printed with the GPU timings of the passes
+pub struct FrameStatistics {
+    enabled: bool,
+    draw_count: u32,
+    update_count: u32,
+    draw_time: f32,
+    update_time: f32,
+    last_report: Instant,
+}
+
+impl FrameStatistics {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            draw_count: 0,
+            update_count: 0,
+            draw_time: 0.0,
+            update_time: 0.0,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.draw_count = 0;
+        self.update_count = 0;
+        self.draw_time = 0.0;
+        self.update_time = 0.0;
+        self.last_report = Instant::now();
+    }
+
+    pub fn record_update(&mut self, update_time: f32) {
+        self.update_count += 1;
+        self.update_time += update_time;
+    }
+
+    pub fn record_draw(&mut self, draw_time: f32) {
+        self.draw_count += 1;
+        self.draw_time += draw_time;
+    }
+
+    pub fn is_report_due(&self) -> bool {
+        self.enabled && self.last_report.elapsed() >= REPORT_INTERVAL
+    }
+
+    // `pass_times` are the GPU times of the passes in seconds, `accumulated_samples` the number
+    // of lighting samples accumulated at the center of the screen
+    pub fn report(
+        &mut self,
+        pass_times: &[(String, f32)],
+        accumulated_samples: f32,
+        texture_memory: usize,
+    ) {
+        let elapsed_time = self.last_report.elapsed().as_secs_f32();
+        let frames_per_second = self.draw_count as f32 / elapsed_time;
+        let average_draw_time = self.draw_time / self.draw_count.max(1) as f32;
+        let average_update_time = self.update_time / self.update_count.max(1) as f32;
+
+        let passes = pass_times
+            .iter()
+            .map(|(name, time)| format!("{} {:.2} ms", name, 1000.0 * time))
+            .collect::<Vec<String>>()
+            .join(", ");
+        println!(
+            "{:.1} FPS | CPU update {:.2} ms, draw {:.2} ms | GPU {} | {} samples | textures {:.1} MB",
+            frames_per_second,
+            1000.0 * average_update_time,
+            1000.0 * average_draw_time,
+            passes,
+            accumulated_samples,
+            texture_memory as f32 / (1024.0 * 1024.0)
+        );
+        self.reset();
+    }
+}
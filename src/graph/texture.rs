@@ -4,10 +4,11 @@ pub struct Texture2D {
     pub id: GLuint,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TextureParameter {
     pub screen_size: (i32, i32),
     pub internal_format: GLenum,
+    pub format: GLenum,
     pub format_type: GLenum,
 }
 
@@ -40,7 +41,7 @@ impl Texture2D {
             parameters.screen_size.0 as i32,
             parameters.screen_size.1 as i32,
             0,
-            gl::RGBA,
+            parameters.format,
             parameters.format_type,
             std::ptr::null(),
         );
@@ -48,6 +49,24 @@ impl Texture2D {
         Self { id: texture_id }
     }
 
+    // Replace the whole texture by `data`, laid out as described by `parameters`
+    pub unsafe fn set_data(&self, parameters: TextureParameter, data: &[u8]) {
+        gl::BindTexture(gl::TEXTURE_2D, self.id);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexSubImage2D(
+            gl::TEXTURE_2D,
+            0,
+            0,
+            0,
+            parameters.screen_size.0,
+            parameters.screen_size.1,
+            parameters.format,
+            parameters.format_type,
+            data.as_ptr() as *const std::ffi::c_void,
+        );
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+    }
+
     pub unsafe fn copy(texture_id_src: GLuint, texture_id_dst: GLuint, window_size: (i32, i32)) {
         gl::ActiveTexture(gl::TEXTURE0);
         gl::BindTexture(gl::TEXTURE_2D, texture_id_src);
@@ -77,19 +96,48 @@ impl Texture2D {
     }
 }
 
+impl Drop for Texture2D {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, [self.id].as_ptr());
+        }
+    }
+}
+
 impl TextureParameter {
     pub fn new_float_parameter(screen_size: (i32, i32)) -> Self {
         TextureParameter {
             screen_size: screen_size,
             internal_format: gl::RGBA32F,
+            format: gl::RGBA,
+            format_type: gl::FLOAT,
+        }
+    }
+
+    pub fn new_depth_parameter(screen_size: (i32, i32)) -> Self {
+        TextureParameter {
+            screen_size,
+            internal_format: gl::DEPTH_COMPONENT32F,
+            format: gl::DEPTH_COMPONENT,
             format_type: gl::FLOAT,
         }
     }
 
+    // One byte per texel, e.g. a font atlas
+    pub fn new_red_byte_parameter(screen_size: (i32, i32)) -> Self {
+        TextureParameter {
+            screen_size,
+            internal_format: gl::R8,
+            format: gl::RED,
+            format_type: gl::UNSIGNED_BYTE,
+        }
+    }
+
     pub fn _new_unsigned_byte_parameter(screen_size: (i32, i32)) -> Self {
         TextureParameter {
             screen_size: screen_size,
             internal_format: gl::RGBA,
+            format: gl::RGBA,
             format_type: gl::UNSIGNED_BYTE,
         }
     }
@@ -0,0 +1,102 @@
+use std::{
+    fs::{read, read_to_string},
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+// Default assets compiled into the binary, so it runs from any directory
+const EMBEDDED_ASSETS: [(&str, &str); 12] = [
+    (
+        "shaders/raymarching_vs.glsl",
+        include_str!("../../assets/shaders/raymarching_vs.glsl"),
+    ),
+    (
+        "shaders/raymarching_fs.glsl",
+        include_str!("../../assets/shaders/raymarching_fs.glsl"),
+    ),
+    (
+        "shaders/lighting_vs.glsl",
+        include_str!("../../assets/shaders/lighting_vs.glsl"),
+    ),
+    (
+        "shaders/lighting_fs.glsl",
+        include_str!("../../assets/shaders/lighting_fs.glsl"),
+    ),
+    (
+        "shaders/filter_vs.glsl",
+        include_str!("../../assets/shaders/filter_vs.glsl"),
+    ),
+    (
+        "shaders/filter_fs.glsl",
+        include_str!("../../assets/shaders/filter_fs.glsl"),
+    ),
+    (
+        "shaders/overlay_vs.glsl",
+        include_str!("../../assets/shaders/overlay_vs.glsl"),
+    ),
+    (
+        "shaders/overlay_fs.glsl",
+        include_str!("../../assets/shaders/overlay_fs.glsl"),
+    ),
+    (
+        "shaders/include/volume.glsl",
+        include_str!("../../assets/shaders/include/volume.glsl"),
+    ),
+    (
+        "render_graph.json",
+        include_str!("../../assets/render_graph.json"),
+    ),
+    ("bindings.json", include_str!("../../assets/bindings.json")),
+    ("camera.json", include_str!("../../assets/camera.json")),
+];
+
+// Binary assets. The font atlas is rendered from DejaVu Sans Mono.
+const EMBEDDED_BINARY_ASSETS: [(&str, &[u8]); 1] = [(
+    "fonts/font.png",
+    include_bytes!("../../assets/fonts/font.png"),
+)];
+
+// Directory whose files replace the embedded assets (e.g. `assets` of the repository to edit
+// shaders and reload them with F5)
+static OVERRIDE_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_override_directory(directory: PathBuf) {
+    if OVERRIDE_DIRECTORY.set(directory).is_err() {
+        println!("Asset directory is already set");
+    }
+}
+
+// Read an asset from its path relative to the assets directory, e.g. `shaders/filter_fs.glsl`
+pub fn read_asset(path: &str) -> std::io::Result<String> {
+    if let Some(overridden_path) = overridden_path(path) {
+        return read_to_string(overridden_path);
+    }
+    EMBEDDED_ASSETS
+        .iter()
+        .find(|(asset_path, _)| *asset_path == path)
+        .map(|(_, content)| content.to_string())
+        .ok_or_else(|| asset_not_found(path))
+}
+
+pub fn read_binary_asset(path: &str) -> std::io::Result<Vec<u8>> {
+    if let Some(overridden_path) = overridden_path(path) {
+        return read(overridden_path);
+    }
+    EMBEDDED_BINARY_ASSETS
+        .iter()
+        .find(|(asset_path, _)| *asset_path == path)
+        .map(|(_, content)| content.to_vec())
+        .ok_or_else(|| asset_not_found(path))
+}
+
+fn overridden_path(path: &str) -> Option<PathBuf> {
+    OVERRIDE_DIRECTORY
+        .get()
+        .map(|directory| directory.join(path))
+        .filter(|overridden_path| overridden_path.is_file())
+}
+
+fn asset_not_found(path: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("no asset {}", path))
+}
@@ -0,0 +1,334 @@
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+use glutin::event::{MouseButton, VirtualKeyCode};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::assets::read_asset;
+
+#[derive(Debug, Error)]
+pub enum BindingsError {
+    #[error("Can't read bindings: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Can't parse bindings: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+// Everything the user can do with the keyboard, the mouse and the gamepad, named in snake
+// case in the bindings files
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveFaster,
+    SpeedUp,
+    SpeedDown,
+    ToggleOrbit,
+    ToggleOrthographic,
+    Look,
+    ToggleLook,
+    // Analog look, e.g. with a gamepad stick
+    LookLeft,
+    LookRight,
+    LookUp,
+    LookDown,
+    OrbitRotate,
+    OrbitPan,
+    ZoomIn,
+    ZoomOut,
+    ResetView,
+    ViewAnterior,
+    ViewPosterior,
+    ViewLeft,
+    ViewRight,
+    ViewSuperior,
+    ViewInferior,
+    // Held while a bookmark action is pressed to save the current view in it
+    SaveBookmark,
+    Bookmark1,
+    Bookmark2,
+    Bookmark3,
+    Bookmark4,
+    Bookmark5,
+    Bookmark6,
+    Bookmark7,
+    Bookmark8,
+    Bookmark9,
+    RenderScaleUp,
+    RenderScaleDown,
+    AutomaticRenderScale,
+    ReloadShaders,
+    RenderSettings,
+    KeyHints,
+    HideOverlay,
+    Statistics,
+    Quit,
+}
+
+impl Action {
+    pub const BOOKMARKS: [Action; 9] = [
+        Action::Bookmark1,
+        Action::Bookmark2,
+        Action::Bookmark3,
+        Action::Bookmark4,
+        Action::Bookmark5,
+        Action::Bookmark6,
+        Action::Bookmark7,
+        Action::Bookmark8,
+        Action::Bookmark9,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WheelDirection {
+    Up,
+    Down,
+}
+
+// Buttons of a gamepad, with the position of the ones of the action pad
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+// Half of an analog axis, whose value goes from 0 at rest to 1
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadAxis {
+    LeftStickUp,
+    LeftStickDown,
+    LeftStickLeft,
+    LeftStickRight,
+    RightStickUp,
+    RightStickDown,
+    RightStickLeft,
+    RightStickRight,
+    LeftTrigger,
+    RightTrigger,
+}
+
+// Input triggering an action, e.g. `{"scancode": 17}`, `{"key": "F5"}`, `{"mouse": "Left"}`,
+// `{"wheel": "up"}`, `{"gamepad_button": "south"}` or `{"gamepad_axis": "left_stick_up"}`
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Binding {
+    // Physical position of the key, the same whatever the keyboard layout. Codes are the
+    // ones of Linux and Windows (set 1), macOS numbers its keys differently.
+    Scancode(u32),
+    // Symbol printed on the key in the current layout
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Wheel(WheelDirection),
+    GamepadButton(GamepadButton),
+    GamepadAxis(GamepadAxis),
+}
+
+impl Binding {
+    pub fn is_gamepad(&self) -> bool {
+        matches!(self, Binding::GamepadButton(_) | Binding::GamepadAxis(_))
+    }
+
+    // Short name shown in the key hints
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Scancode(scancode) => match scancode_name(*scancode) {
+                Some(name) => name.to_string(),
+                None => format!("#{}", scancode),
+            },
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("{:?} button", button),
+            Binding::Wheel(WheelDirection::Up) => "Wheel up".to_string(),
+            Binding::Wheel(WheelDirection::Down) => "Wheel down".to_string(),
+            Binding::GamepadButton(button) => format!("Pad {:?}", button),
+            Binding::GamepadAxis(axis) => format!("Pad {:?}", axis),
+        }
+    }
+}
+
+// Keys of an US QWERTY keyboard at the scancodes used by the default bindings
+fn scancode_name(scancode: u32) -> Option<&'static str> {
+    let name = match scancode {
+        2 => "1",
+        3 => "2",
+        4 => "3",
+        5 => "4",
+        6 => "5",
+        7 => "6",
+        8 => "7",
+        9 => "8",
+        10 => "9",
+        16 => "Q",
+        17 => "W",
+        18 => "E",
+        29 => "LControl",
+        30 => "A",
+        31 => "S",
+        32 => "D",
+        24 => "O",
+        38 => "L",
+        42 => "LShift",
+        46 => "C",
+        56 => "LAlt",
+        57 => "Space",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// Actions sharing an input on purpose: the wheel changes the speed in first person and zooms
+// with the orbit camera
+const SHARED_INPUTS: [(Action, Action); 2] = [
+    (Action::SpeedUp, Action::ZoomIn),
+    (Action::SpeedDown, Action::ZoomOut),
+];
+
+// Inputs bound to each action. The defaults are read from the `bindings.json` asset, a user
+// file replaces the bindings of the actions it lists.
+pub struct Bindings {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    pub fn load(user_file: Option<&Path>) -> Result<Self, BindingsError> {
+        let mut bindings: HashMap<Action, Vec<Binding>> =
+            serde_json::from_str(&read_asset("bindings.json")?)?;
+        if let Some(user_file) = user_file {
+            let user_bindings: HashMap<Action, Vec<Binding>> =
+                serde_json::from_str(&read_to_string(user_file)?)?;
+            bindings.extend(user_bindings);
+        }
+        Ok(Self { bindings })
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    // Inputs bound to several actions, e.g. `LControl: MoveDown, SaveBookmark`. Holding one
+    // of them would trigger all its actions at once.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut inputs: Vec<(Binding, Vec<Action>)> = Vec::new();
+        for (action, bindings) in &self.bindings {
+            for binding in bindings {
+                match inputs.iter_mut().find(|(input, _)| input == binding) {
+                    Some((_, actions)) => actions.push(*action),
+                    None => inputs.push((*binding, vec![*action])),
+                }
+            }
+        }
+        let mut conflicts = inputs
+            .into_iter()
+            .filter(|(_, actions)| {
+                actions.len() > 1
+                    && !SHARED_INPUTS.iter().any(|(first, second)| {
+                        actions.len() == 2 && actions.contains(first) && actions.contains(second)
+                    })
+            })
+            .map(|(input, actions)| {
+                let mut action_names = actions
+                    .iter()
+                    .map(|action| format!("{:?}", action))
+                    .collect::<Vec<String>>();
+                action_names.sort();
+                format!("{}: {}", input.name(), action_names.join(", "))
+            })
+            .collect::<Vec<String>>();
+        conflicts.sort();
+        conflicts
+    }
+
+    // Names of the keyboard and mouse inputs bound to an action, e.g. `W / Up`
+    pub fn names(&self, action: Action) -> String {
+        let names = self
+            .get(action)
+            .iter()
+            .filter(|binding| !binding.is_gamepad())
+            .map(Binding::name)
+            .collect::<Vec<String>>();
+        if names.is_empty() {
+            "-".to_string()
+        } else {
+            names.join(" / ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{remove_file, write};
+
+    use super::*;
+
+    fn load_with_user_file(name: &str, content: &str) -> Bindings {
+        let path = std::env::temp_dir().join(format!("bol_{}_{}.json", name, std::process::id()));
+        write(&path, content).unwrap();
+        let bindings = Bindings::load(Some(&path)).unwrap();
+        remove_file(path).unwrap();
+        bindings
+    }
+
+    #[test]
+    fn default_bindings_have_no_conflicts() {
+        let bindings = Bindings::load(None).unwrap();
+        assert_eq!(bindings.conflicts(), Vec::<String>::new());
+        assert_eq!(bindings.names(Action::MoveForward), "W / Up");
+    }
+
+    #[test]
+    fn user_file_replaces_only_the_listed_actions() {
+        let bindings = load_with_user_file(
+            "override",
+            r#"{ "move_forward": [{ "key": "I" }], "quit": [] }"#,
+        );
+        assert_eq!(
+            bindings.get(Action::MoveForward),
+            &[Binding::Key(VirtualKeyCode::I)]
+        );
+        assert!(bindings.get(Action::Quit).is_empty());
+        assert_eq!(bindings.names(Action::MoveBackward), "S / Down");
+    }
+
+    #[test]
+    fn inputs_bound_to_several_actions_are_reported() {
+        let bindings = load_with_user_file(
+            "conflict",
+            r#"{ "move_down": [{ "scancode": 56 }], "speed_up": [{ "key": "Home" }] }"#,
+        );
+        assert_eq!(
+            bindings.conflicts(),
+            vec![
+                "Home: ResetView, SpeedUp".to_string(),
+                "LAlt: MoveDown, SaveBookmark".to_string(),
+            ]
+        );
+    }
+}
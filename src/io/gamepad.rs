@@ -0,0 +1,107 @@
+#[cfg(feature = "gamepad")]
+use gilrs::{Axis, Button, EventType, Gilrs};
+
+#[cfg(feature = "gamepad")]
+use super::bindings::{GamepadAxis, GamepadButton};
+use super::input_player::InputManager;
+
+// Feeds the gamepad buttons and axes to the input manager. Gamepads are read with gilrs when
+// the `gamepad` feature is enabled (it needs libudev on Linux), only the sticks, triggers and
+// buttons are mapped. Without the feature there is no gamepad.
+pub struct Gamepads {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<Gilrs>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "gamepad")]
+            gilrs: match Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(error) => {
+                    println!("Gamepads are not available: {}", error);
+                    None
+                }
+            },
+        }
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn update(&mut self, _input_manager: &mut InputManager) {}
+
+    #[cfg(feature = "gamepad")]
+    pub fn update(&mut self, input_manager: &mut InputManager) {
+        let Some(gilrs) = self.gilrs.as_mut() else {
+            return;
+        };
+        // Values are already filtered by the dead zone of gilrs
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    if let Some(button) = Self::button(button) {
+                        input_manager.gamepad_button_event(button, true);
+                    }
+                }
+                EventType::ButtonReleased(button, _) => {
+                    if let Some(button) = Self::button(button) {
+                        input_manager.gamepad_button_event(button, false);
+                    }
+                }
+                // The analog triggers are reported as buttons with a value
+                EventType::ButtonChanged(Button::LeftTrigger2, value, _) => {
+                    input_manager.update_gamepad_axis(GamepadAxis::LeftTrigger, value)
+                }
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) => {
+                    input_manager.update_gamepad_axis(GamepadAxis::RightTrigger, value)
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    if let Some((positive, negative)) = Self::axis(axis) {
+                        input_manager.update_gamepad_axis(positive, value);
+                        input_manager.update_gamepad_axis(negative, -value);
+                    }
+                }
+                EventType::Connected => {
+                    println!("Gamepad {} connected", gilrs.gamepad(event.id).name())
+                }
+                EventType::Disconnected => input_manager.reset_gamepad(),
+                _ => {}
+            }
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn button(button: Button) -> Option<GamepadButton> {
+        let button = match button {
+            Button::South => GamepadButton::South,
+            Button::East => GamepadButton::East,
+            Button::North => GamepadButton::North,
+            Button::West => GamepadButton::West,
+            Button::LeftTrigger => GamepadButton::LeftBumper,
+            Button::RightTrigger => GamepadButton::RightBumper,
+            Button::Select => GamepadButton::Select,
+            Button::Start => GamepadButton::Start,
+            Button::LeftThumb => GamepadButton::LeftThumb,
+            Button::RightThumb => GamepadButton::RightThumb,
+            Button::DPadUp => GamepadButton::DPadUp,
+            Button::DPadDown => GamepadButton::DPadDown,
+            Button::DPadLeft => GamepadButton::DPadLeft,
+            Button::DPadRight => GamepadButton::DPadRight,
+            _ => return None,
+        };
+        Some(button)
+    }
+
+    // Halves of an axis at its positive and negative values. The Y axes of gilrs point up.
+    #[cfg(feature = "gamepad")]
+    fn axis(axis: Axis) -> Option<(GamepadAxis, GamepadAxis)> {
+        let halves = match axis {
+            Axis::LeftStickX => (GamepadAxis::LeftStickRight, GamepadAxis::LeftStickLeft),
+            Axis::LeftStickY => (GamepadAxis::LeftStickUp, GamepadAxis::LeftStickDown),
+            Axis::RightStickX => (GamepadAxis::RightStickRight, GamepadAxis::RightStickLeft),
+            Axis::RightStickY => (GamepadAxis::RightStickUp, GamepadAxis::RightStickDown),
+            _ => return None,
+        };
+        Some(halves)
+    }
+}
@@ -1,11 +1,12 @@
-use std::{collections::HashMap, time::Instant};
+use std::{collections::HashMap, hash::Hash};
 
 use cgmath::Vector2;
-use glutin::event::VirtualKeyCode;
+use glutin::event::{MouseButton, VirtualKeyCode};
+
+use super::bindings::{Action, Binding, Bindings, GamepadAxis, GamepadButton, WheelDirection};
 
 #[derive(Debug)]
 struct KeyInformation {
-    pub time_pressed: Instant,
     pub is_pressed: bool,
     pub once: bool,
 }
@@ -17,7 +18,22 @@ struct MouseMoved {
 
 pub struct InputManager {
     keys: HashMap<VirtualKeyCode, KeyInformation>,
+    scancodes: HashMap<u32, KeyInformation>,
+    bindings: Bindings,
     mouse_moved: MouseMoved,
+    // Lines scrolled with the mouse wheel since the last reset, positive away from the user
+    scroll_delta: f32,
+    // Position in pixels from the top left corner, None outside the window
+    cursor_position: Option<(f32, f32)>,
+    // Buttons held down, with true until the press is read by `is_mouse_pressed_once`
+    mouse_buttons: HashMap<MouseButton, bool>,
+    gamepad_buttons: HashMap<GamepadButton, KeyInformation>,
+    // Value of the gamepad axes, from 0 to 1
+    gamepad_axes: HashMap<GamepadAxis, f32>,
+    // Wheel directions which already triggered an action since the last reset
+    wheel_triggered: Vec<WheelDirection>,
+    // Raw mouse motion is received even when the window is not focused, it is ignored then
+    focused: bool,
 }
 
 impl InputManager {
@@ -26,105 +42,246 @@ impl InputManager {
 
         Self {
             keys,
+            scancodes: HashMap::new(),
+            bindings: Bindings::new(),
             mouse_moved: MouseMoved {
                 delta_mouse: Vector2 { x: 0.0, y: 0.0 },
             },
+            scroll_delta: 0.0,
+            cursor_position: None,
+            mouse_buttons: HashMap::new(),
+            gamepad_buttons: HashMap::new(),
+            gamepad_axes: HashMap::new(),
+            wheel_triggered: Vec::new(),
+            focused: true,
         }
     }
 
-    pub fn key_event_pressed(&mut self, input: VirtualKeyCode) {
-        self.key_update(input, true);
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
     }
 
-    pub fn key_event_released(&mut self, input: VirtualKeyCode) {
-        self.key_update(input, false);
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
     }
 
-    fn key_update(&mut self, input: VirtualKeyCode, is_pressed: bool) {
-        let key_info = self.keys.get(&input);
-        // If key has already been pressed
-        if Option::is_some(&key_info) {
-            let was_pressed = key_info.as_ref().unwrap().is_pressed;
-            // If the key is pressed and was not pressed before
-            if is_pressed && !was_pressed {
-                self.keys.insert(
-                    input,
-                    KeyInformation {
-                        time_pressed: Instant::now(),
-                        is_pressed: true,
-                        once: true,
-                    },
-                );
-            }
+    // Keys are tracked both by position and by symbol, as bindings can use either
+    pub fn key_event(
+        &mut self,
+        scancode: u32,
+        virtual_key_code: Option<VirtualKeyCode>,
+        is_pressed: bool,
+    ) {
+        key_update(&mut self.scancodes, scancode, is_pressed);
+        if let Some(virtual_key_code) = virtual_key_code {
+            key_update(&mut self.keys, virtual_key_code, is_pressed);
+        }
+    }
 
-            // If keys was released
-            if !is_pressed && was_pressed {
-                self.keys.insert(
-                    input,
-                    KeyInformation {
-                        time_pressed: Instant::now(),
-                        is_pressed: false,
-                        once: false,
-                    },
-                );
-            }
+    // Gamepads are read even when the window is not focused, they are ignored then
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn gamepad_button_event(&mut self, button: GamepadButton, is_pressed: bool) {
+        if !self.focused {
+            return;
+        }
+        key_update(&mut self.gamepad_buttons, button, is_pressed);
+    }
 
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn update_gamepad_axis(&mut self, axis: GamepadAxis, value: f32) {
+        if !self.focused {
             return;
         }
+        self.gamepad_axes.insert(axis, value.clamp(0.0, 1.0));
+    }
 
-        self.keys.insert(
-            input,
-            KeyInformation {
-                time_pressed: Instant::now(),
-                is_pressed: is_pressed,
-                once: is_pressed,
-            },
-        );
+    // Forget the state of the gamepads, e.g. when one is disconnected
+    pub fn reset_gamepad(&mut self) {
+        self.gamepad_buttons.clear();
+        self.gamepad_axes.clear();
     }
 
     pub fn update_mouse(&mut self, delta: (f64, f64)) {
+        if !self.focused {
+            return;
+        }
         self.mouse_moved.delta_mouse += Vector2::new(delta.0 as f32, delta.1 as f32);
     }
 
-    pub fn is_pressed(&mut self, virtual_key_code: VirtualKeyCode) -> bool {
-        let key = self.keys.get(&virtual_key_code);
-        if key.is_some() {
-            if key.as_ref().unwrap().is_pressed {
-                let key_updated = KeyInformation {
-                    time_pressed: key.unwrap().time_pressed,
-                    is_pressed: key.unwrap().is_pressed,
-                    once: false,
-                };
-                self.keys.insert(virtual_key_code, key_updated);
-                return true;
-            }
-            return false;
+    pub fn update_scroll(&mut self, lines: f32) {
+        self.scroll_delta += lines;
+    }
+
+    pub fn update_cursor_position(&mut self, cursor_position: Option<(f32, f32)>) {
+        self.cursor_position = cursor_position;
+    }
+
+    pub fn cursor_position(&self) -> Option<(f32, f32)> {
+        self.cursor_position
+    }
+
+    pub fn mouse_button_event(&mut self, button: MouseButton, is_pressed: bool) {
+        if is_pressed {
+            self.mouse_buttons.entry(button).or_insert(true);
+        } else {
+            self.mouse_buttons.remove(&button);
         }
-        return false;
     }
 
-    pub fn is_pressed_once(&mut self, virtual_key_code: VirtualKeyCode) -> bool {
-        let key = self.keys.get(&virtual_key_code);
-        if key.is_some() {
-            if key.as_ref().unwrap().is_pressed && key.as_ref().unwrap().once {
-                let key_updated = KeyInformation {
-                    time_pressed: key.unwrap().time_pressed,
-                    is_pressed: key.unwrap().is_pressed,
-                    once: false,
-                };
-                self.keys.insert(virtual_key_code, key_updated);
-                return true;
+    pub fn is_mouse_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.contains_key(&button)
+    }
+
+    pub fn is_mouse_pressed_once(&mut self, button: MouseButton) -> bool {
+        match self.mouse_buttons.get_mut(&button) {
+            Some(once) => std::mem::replace(once, false),
+            None => false,
+        }
+    }
+
+    // True while one of the inputs bound to the action is held
+    pub fn is_action_pressed(&mut self, action: Action) -> bool {
+        self.action_amount(action) > 0.0
+    }
+
+    // True once per press of one of the inputs bound to the action. A wheel binding
+    // triggers once per frame in which the wheel turned, gamepad axes never trigger.
+    pub fn is_action_pressed_once(&mut self, action: Action) -> bool {
+        let mut pressed = false;
+        for binding in self.bindings.get(action).to_vec() {
+            pressed |= match binding {
+                Binding::Scancode(scancode) => read_key(&mut self.scancodes, scancode, true),
+                Binding::Key(key) => read_key(&mut self.keys, key, true),
+                Binding::Mouse(button) => self.is_mouse_pressed_once(button),
+                Binding::Wheel(direction) => {
+                    let triggered = self.wheel_amount(direction) > 0.0
+                        && !self.wheel_triggered.contains(&direction);
+                    if triggered {
+                        self.wheel_triggered.push(direction);
+                    }
+                    triggered
+                }
+                Binding::GamepadButton(button) => read_key(&mut self.gamepad_buttons, button, true),
+                Binding::GamepadAxis(_) => false,
+            };
+        }
+        pressed
+    }
+
+    // 1 while a key or button bound to the action is held, otherwise the value of the most
+    // pushed gamepad axis, plus the lines scrolled with a bound wheel direction since the
+    // last reset
+    pub fn action_amount(&mut self, action: Action) -> f32 {
+        let mut amount: f32 = 0.0;
+        let mut analog_amount: f32 = 0.0;
+        let mut held = false;
+        for binding in self.bindings.get(action).to_vec() {
+            match binding {
+                Binding::Scancode(scancode) => {
+                    held |= read_key(&mut self.scancodes, scancode, false)
+                }
+                Binding::Key(key) => held |= read_key(&mut self.keys, key, false),
+                Binding::Mouse(button) => held |= self.is_mouse_pressed(button),
+                Binding::Wheel(direction) => amount += self.wheel_amount(direction),
+                Binding::GamepadButton(button) => {
+                    held |= read_key(&mut self.gamepad_buttons, button, false)
+                }
+                Binding::GamepadAxis(axis) => {
+                    let value = self.gamepad_axes.get(&axis).copied().unwrap_or(0.0);
+                    analog_amount = analog_amount.max(value);
+                }
             }
-            return false;
         }
-        return false;
+        if held {
+            analog_amount = 1.0;
+        }
+        amount + analog_amount
+    }
+
+    fn wheel_amount(&self, direction: WheelDirection) -> f32 {
+        match direction {
+            WheelDirection::Up => self.scroll_delta.max(0.0),
+            WheelDirection::Down => (-self.scroll_delta).max(0.0),
+        }
+    }
+
+    // The release of keys and buttons held when the focus is lost is never received
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        if !focused {
+            self.keys.clear();
+            self.scancodes.clear();
+            self.mouse_buttons.clear();
+            self.reset_gamepad();
+            self.reset_delta();
+        }
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
     }
 
     pub fn reset_delta(&mut self) {
         self.mouse_moved.delta_mouse = Vector2::new(0.0, 0.0);
+        self.scroll_delta = 0.0;
+        self.wheel_triggered.clear();
     }
 
     pub fn get_delta(&self) -> Vector2<f32> {
         self.mouse_moved.delta_mouse
     }
 }
+
+fn key_update<K: Copy + Eq + Hash>(
+    keys: &mut HashMap<K, KeyInformation>,
+    input: K,
+    is_pressed: bool,
+) {
+    let key_info = keys.get(&input);
+    // If key has already been pressed
+    if Option::is_some(&key_info) {
+        let was_pressed = key_info.as_ref().unwrap().is_pressed;
+        // If the key is pressed and was not pressed before
+        if is_pressed && !was_pressed {
+            keys.insert(
+                input,
+                KeyInformation {
+                    is_pressed: true,
+                    once: true,
+                },
+            );
+        }
+
+        // If keys was released
+        if !is_pressed && was_pressed {
+            keys.insert(
+                input,
+                KeyInformation {
+                    is_pressed: false,
+                    once: false,
+                },
+            );
+        }
+
+        return;
+    }
+
+    keys.insert(
+        input,
+        KeyInformation {
+            is_pressed,
+            once: is_pressed,
+        },
+    );
+}
+
+// Return true if the key is held, only once per press if `once` is set
+fn read_key<K: Eq + Hash>(keys: &mut HashMap<K, KeyInformation>, input: K, once: bool) -> bool {
+    match keys.get_mut(&input) {
+        Some(key) if key.is_pressed && (key.once || !once) => {
+            key.once = false;
+            true
+        }
+        _ => false,
+    }
+}
@@ -1 +1,5 @@
+pub mod assets;
+pub mod bindings;
+pub mod gamepad;
 pub mod input_player;
+pub mod mouse_look;
@@ -0,0 +1,59 @@
+use glutin::window::{CursorGrabMode, Window};
+
+use super::{bindings::Action, input_player::InputManager};
+
+// Look mode: the mouse turns the camera while the look action is held or after the toggle
+// action, the cursor is then hidden and kept in the window. Outside of it the mouse is free
+// to click on the overlay.
+pub struct MouseLook {
+    toggled: bool,
+    active: bool,
+}
+
+impl MouseLook {
+    pub fn new() -> Self {
+        Self {
+            toggled: false,
+            active: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // `allowed` is false while the mouse is used by something else (e.g. the settings panel).
+    // Look mode is left when the window loses the focus.
+    pub fn update(&mut self, input_manager: &mut InputManager, window: &Window, allowed: bool) {
+        if input_manager.is_action_pressed_once(Action::ToggleLook) {
+            self.toggled = !self.toggled;
+        }
+        if !input_manager.is_focused() {
+            self.toggled = false;
+        }
+        let active = allowed
+            && input_manager.is_focused()
+            && (self.toggled || input_manager.is_action_pressed(Action::Look));
+        if active != self.active {
+            self.active = active;
+            Self::grab_cursor(window, active);
+            // Movements made before entering look mode do not turn the camera
+            input_manager.reset_delta();
+        }
+    }
+
+    fn grab_cursor(window: &Window, grab: bool) {
+        let result = if grab {
+            // X11 can only confine the cursor to the window, it is not locked in place
+            window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(error) = result {
+            println!("Can't grab the cursor: {}", error);
+        }
+        window.set_cursor_visible(!grab);
+    }
+}
@@ -1,35 +1,119 @@
-use std::{env, process::exit, time::Instant};
+use std::{env, path::PathBuf, process::exit, time::Instant};
 
 use cgmath::{Vector2, Vector3};
 use glutin::{
     dpi::PhysicalSize,
-    event::{DeviceEvent, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event::{DeviceEvent, ElementState, Event, KeyboardInput, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::{Window, WindowBuilder},
     Api, ContextBuilder, ContextWrapper, GlRequest, PossiblyCurrent,
 };
-use graph::draw::Drawer;
-use io::input_player::InputManager;
-use player::player::Player;
-use world::world_data::WorldData;
+use graph::{draw::Drawer, statistics::FrameStatistics};
+use io::{
+    assets,
+    bindings::{Action, Bindings},
+    gamepad::Gamepads,
+    input_player::InputManager,
+    mouse_look::MouseLook,
+};
+use player::{
+    bookmarks::Bookmarks,
+    camera::ProjectionSettings,
+    player::Player,
+};
+use world::{
+    loader::{LoadStatus, VolumeLoader},
+    world_data::WorldData,
+};
 
 mod graph;
 mod io;
 mod player;
 mod world;
 
+const WINDOW_TITLE: &str = "IRM Visualizer";
+// Touchpads scroll in pixels, converted to wheel lines
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Programm need one argument: path to bin file");
+    let mut args = env::args().skip(1);
+    let mut stream_volume = false;
+    let mut file_names = Vec::new();
+    let mut bindings_file = None;
+    // Replacements of the settings of the `camera.json` asset
+    let mut field_of_view = None;
+    let mut near_plane = None;
+    let mut far_plane = None;
+    let mut max_ray_distance = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Keep the volume on disk and upload only the visible bricks
+            "--stream" => stream_volume = true,
+            // Read shaders and assets from a directory instead of the ones embedded in the binary
+            "--assets" => match args.next() {
+                Some(directory) => assets::set_override_directory(PathBuf::from(directory)),
+                None => {
+                    println!("--assets needs a directory");
+                    exit(-1);
+                }
+            },
+            // Replace the default bindings of the actions listed in a json file
+            "--bindings" => match args.next() {
+                Some(file) => bindings_file = Some(PathBuf::from(file)),
+                None => {
+                    println!("--bindings needs a file");
+                    exit(-1);
+                }
+            },
+            // Vertical field of view in degrees
+            "--fov" => field_of_view = Some(float_argument(&mut args, "--fov")),
+            "--near" => near_plane = Some(float_argument(&mut args, "--near")),
+            "--far" => far_plane = Some(float_argument(&mut args, "--far")),
+            "--max-ray-distance" => {
+                max_ray_distance = Some(float_argument(&mut args, "--max-ray-distance"))
+            }
+            _ => file_names.push(arg),
+        }
+    }
+    if file_names.len() != 1 {
+        println!(
+            "Programm need one argument: path to bin file (optionally --stream, --assets <directory>, --bindings <file>, --fov <degrees>, --near <distance>, --far <distance>, --max-ray-distance <distance>)"
+        );
         exit(-1);
     }
+    let bindings = match Bindings::load(bindings_file.as_deref()) {
+        Ok(bindings) => bindings,
+        Err(error) => {
+            println!("{}", error);
+            exit(-1);
+        }
+    };
+    for conflict in bindings.conflicts() {
+        println!("Input bound to several actions: {}", conflict);
+    }
 
-    let file_name_to_plot = &args[1];
+    let mut projection = match ProjectionSettings::load() {
+        Ok(projection) => projection,
+        Err(error) => {
+            println!("{}", error);
+            exit(-1);
+        }
+    };
+    projection.field_of_view = field_of_view.unwrap_or(projection.field_of_view);
+    projection.near_plane = near_plane.unwrap_or(projection.near_plane);
+    projection.far_plane = far_plane.unwrap_or(projection.far_plane);
+    projection.max_ray_distance = max_ray_distance.unwrap_or(projection.max_ray_distance);
+    if let Err(error) = projection.validate() {
+        println!("{}", error);
+        exit(-1);
+    }
+
+    let file_name_to_plot = &file_names[0];
 
     unsafe {
         let event_loop = EventLoop::new();
-        let mut game = Game::new(&event_loop, file_name_to_plot);
+        let mut game = Game::new(&event_loop, file_name_to_plot, stream_volume, projection);
+        game.input_manager.set_bindings(bindings);
         game.load_content();
         // Infinite loop of the code
         event_loop.run(move |event, _, control_flow| {
@@ -39,28 +123,38 @@ fn main() {
                 Event::LoopDestroyed => (),
                 Event::WindowEvent { event, .. } => match event {
                     WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(physical_size) => game.gl_context.resize(physical_size),
+                    WindowEvent::Resized(physical_size) => game.resize(physical_size),
                     WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
-                                virtual_keycode: Some(key),
-                                state: glutin::event::ElementState::Pressed,
+                                scancode,
+                                virtual_keycode,
+                                state,
                                 ..
                             },
                         ..
-                    } => {
-                        game.input_manager.key_event_pressed(key);
+                    } => game.input_manager.key_event(
+                        scancode,
+                        virtual_keycode,
+                        state == ElementState::Pressed,
+                    ),
+                    WindowEvent::CursorMoved { position, .. } => game
+                        .input_manager
+                        .update_cursor_position(Some((position.x as f32, position.y as f32))),
+                    WindowEvent::MouseInput { state, button, .. } => game
+                        .input_manager
+                        .mouse_button_event(button, state == ElementState::Pressed),
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        game.input_manager.update_scroll(match delta {
+                            MouseScrollDelta::LineDelta(_, lines) => lines,
+                            MouseScrollDelta::PixelDelta(position) => {
+                                position.y as f32 / PIXELS_PER_SCROLL_LINE
+                            }
+                        })
                     }
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                virtual_keycode: Some(key),
-                                state: glutin::event::ElementState::Released,
-                                ..
-                            },
-                        ..
-                    } => {
-                        game.input_manager.key_event_released(key);
+                    WindowEvent::Focused(focused) => game.input_manager.set_focused(focused),
+                    WindowEvent::CursorLeft { .. } => {
+                        game.input_manager.update_cursor_position(None)
                     }
                     _ => (),
                 },
@@ -80,6 +174,17 @@ fn main() {
     }
 }
 
+// Value following a command line option, e.g. `--fov 60`
+fn float_argument(args: &mut impl Iterator<Item = String>, option: &str) -> f32 {
+    match args.next().map(|value| value.parse::<f32>()) {
+        Some(Ok(value)) if value > 0.0 => value,
+        _ => {
+            println!("{} needs a positive number", option);
+            exit(-1);
+        }
+    }
+}
+
 struct Game {
     gl_context: ContextWrapper<PossiblyCurrent, Window>,
     input_manager: InputManager,
@@ -90,13 +195,24 @@ struct Game {
     world_data: WorldData,
     drawer: Drawer,
     file_name: String,
+    stream_volume: bool,
+    volume_loader: Option<VolumeLoader>,
+    statistics: FrameStatistics,
+    mouse_look: MouseLook,
+    gamepads: Gamepads,
+    bookmarks: Bookmarks,
 }
 
 impl Game {
-    unsafe fn new(event_loop: &EventLoop<()>, file_name: &String) -> Self {
+    unsafe fn new(
+        event_loop: &EventLoop<()>,
+        file_name: &String,
+        stream_volume: bool,
+        projection: ProjectionSettings,
+    ) -> Self {
         let input_manager: InputManager = InputManager::new();
         let window = WindowBuilder::new()
-            .with_title("IRM Visualizer")
+            .with_title(WINDOW_TITLE)
             .with_inner_size(PhysicalSize::new(1024, 768));
 
         let gl_context = ContextBuilder::new()
@@ -122,63 +238,183 @@ impl Game {
             time_since_beginning: 0.0,
             camera: Player::new(Vector3::new(-0.0, 0.0, 0.0), 1.0),
             world_data: WorldData::new(Vector2::new(0.01, 0.005)),
-            drawer: Drawer::new(),
+            drawer: Drawer::new(projection),
             file_name: file_name.to_string(),
+            stream_volume,
+            volume_loader: None,
+            statistics: FrameStatistics::new(),
+            mouse_look: MouseLook::new(),
+            gamepads: Gamepads::new(),
+            bookmarks: Bookmarks::load(file_name),
         }
     }
 
     unsafe fn load_content(&mut self) {
         let aspect_ratio = (self.gl_context.window().inner_size().width as f32)
             / (self.gl_context.window().inner_size().height as f32);
-        self.camera = Player::new(Vector3::new(0.05, 0.05, -0.1), aspect_ratio);
+        self.camera = Player::new(Vector3::new(0.0, 0.0, 0.0), aspect_ratio);
+        self.camera
+            .set_projection_settings(self.drawer.render_settings().camera_projection);
+        // The view is framed again once the volume is loaded
+        let (minimum, maximum) = self.world_data.content_bounds();
+        self.camera.set_volume_bounds(minimum, maximum);
+        self.camera.reset_view();
 
         //self.world_data.generate_bottle();
-        self.world_data
-            .load_world_from_file(self.file_name.as_str());
+        // Volume is read on a worker thread and uploaded in update() once decoded
+        self.volume_loader = Some(VolumeLoader::start(
+            self.file_name.as_str(),
+            self.stream_volume,
+        ));
         //self.world_data.generate_random();
 
         self.drawer.load_content(&self.gl_context);
     }
 
+    unsafe fn resize(&mut self, physical_size: PhysicalSize<u32>) {
+        self.gl_context.resize(physical_size);
+        // Minimized window
+        if physical_size.width == 0 || physical_size.height == 0 {
+            return;
+        }
+
+        self.drawer.resize((
+            physical_size.width as i32,
+            physical_size.height as i32,
+        ));
+        self.camera
+            .camera
+            .set_aspect_ratio(physical_size.width as f32 / physical_size.height as f32);
+    }
+
     fn update(&mut self) -> ControlFlow {
-        // Quit game when escape is pressed
-        if self.input_manager.is_pressed(VirtualKeyCode::Escape) {
+        self.gamepads.update(&mut self.input_manager);
+
+        // Quit game when the quit action is triggered
+        if self.input_manager.is_action_pressed(Action::Quit) {
             self.end();
             return ControlFlow::Exit;
         }
 
         // Update time
+        let time_update_start = Instant::now();
         let time_since_last_update = self.time_last_update.elapsed().as_secs_f32();
         self.time_last_update = Instant::now();
         self.time_since_beginning += time_since_last_update;
 
+        // Upload the volume once the loader thread is done
+        if let Some(volume_loader) = self.volume_loader.as_mut() {
+            match volume_loader.poll() {
+                LoadStatus::Finished(loaded_volume) => {
+                    self.world_data.set_loaded_volume(loaded_volume);
+                    // Frame the visible voxels of the new volume
+                    let (minimum, maximum) = self.world_data.content_bounds();
+                    self.camera.set_volume_bounds(minimum, maximum);
+                    self.camera.reset_view();
+                    self.volume_loader = None;
+                    self.gl_context.window().set_title(WINDOW_TITLE);
+                }
+                LoadStatus::Failed(error) => {
+                    println!("Can't load volume {}", error);
+                    self.end();
+                    return ControlFlow::Exit;
+                }
+                LoadStatus::Loading {
+                    progress_changed: true,
+                } => self.gl_context.window().set_title(&format!(
+                    "{} - loading {:.0}%",
+                    WINDOW_TITLE,
+                    100.0 * volume_loader.progress()
+                )),
+                LoadStatus::Loading { .. } => {}
+            }
+        }
+
         // Update drawer
         self.drawer.update(&mut self.input_manager);
 
+        // Frame statistics are printed periodically once enabled
+        if self.input_manager.is_action_pressed_once(Action::Statistics) {
+            self.statistics.toggle();
+        }
+        self.statistics
+            .record_update(time_update_start.elapsed().as_secs_f32());
+
         return ControlFlow::Poll;
     }
 
     unsafe fn draw(&mut self) {
         // Update time
+        let time_draw_start = Instant::now();
         let time_since_last_draw = self.time_last_draw.elapsed().as_secs_f32();
         self.time_last_draw = Instant::now();
 
-        // Update camera
+        // Update camera, the mouse is left to the settings panel while it is open
+        if self.drawer.is_gui_visible() {
+            self.input_manager.reset_delta();
+        }
+        self.mouse_look.update(
+            &mut self.input_manager,
+            self.gl_context.window(),
+            !self.drawer.is_gui_visible(),
+        );
+        if let Some(render_settings) = self.bookmarks.update(
+            &mut self.input_manager,
+            &mut self.camera,
+            self.drawer.render_settings(),
+        ) {
+            self.drawer.set_render_settings(render_settings);
+        }
+        self.camera
+            .set_motion_settings(self.drawer.render_settings().camera_motion);
+        self.camera
+            .set_projection_settings(self.drawer.render_settings().camera_projection);
         self.camera
-            .update(&mut self.input_manager, time_since_last_draw);
+            .update(
+                &mut self.input_manager,
+                time_since_last_draw,
+                self.mouse_look.is_active(),
+            );
         self.camera.camera.recalculate_matrix();
 
+        // Upload the bricks of a streamed volume seen by the camera
+        self.world_data.update_streaming(
+            self.camera.get_eye_position(),
+            self.camera.camera.get_mvp_matrix(),
+            self.camera.camera.get_projection().max_ray_distance,
+        );
+
         gl::ClearColor(0.5, 0.5, 0.5, 1.0);
         gl::Clear(gl::COLOR_BUFFER_BIT);
 
         self.drawer.draw(
-            &self.gl_context,
             &self.camera,
             self.time_since_beginning,
             &self.world_data,
             self.world_data.voxel_size,
         );
 
+        if let Some(volume_loader) = self.volume_loader.as_ref() {
+            self.drawer
+                .draw_progress_bar(&self.gl_context, volume_loader.progress());
+        }
+        self.drawer.draw_overlay(
+            &self.file_name,
+            &self.camera,
+            &self.world_data,
+            &mut self.input_manager,
+        );
+
+        self.statistics
+            .record_draw(time_draw_start.elapsed().as_secs_f32());
+        if self.statistics.is_report_due() {
+            self.statistics.report(
+                &self.drawer.pass_times(),
+                self.drawer.accumulated_samples(),
+                self.drawer.texture_memory() + self.world_data.texture_memory(),
+            );
+        }
+
         self.gl_context.swap_buffers().unwrap();
     }
 
@@ -0,0 +1,189 @@
+use std::{
+    fs::{read_to_string, write},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use cgmath::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    graph::render_settings::RenderSettings,
+    io::{bindings::Action, input_player::InputManager},
+    world::loader::VolumeLoader,
+};
+
+use super::{
+    camera::ProjectionSettings,
+    player::{CameraView, Player},
+};
+
+#[derive(Debug, Error)]
+pub enum BookmarksError {
+    #[error("Can't access bookmarks: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Can't parse bookmarks: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+// View saved in a slot, with the settings needed to see the same thing again
+#[derive(Deserialize, Serialize)]
+struct Bookmark {
+    // 1 to 9, the number key recalling it
+    slot: usize,
+    // Can be edited in the file to describe the view
+    name: String,
+    position: [f32; 3],
+    rotation: [f32; 2],
+    // Up direction of the camera, bookmarks saved before it existed use the Y axis
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+    // Target of the orbit camera, absent for the first person camera
+    #[serde(default)]
+    orbit_target: Option<[f32; 3]>,
+    #[serde(default)]
+    orthographic: bool,
+    // Bookmarks saved before it existed keep the current projection
+    #[serde(default)]
+    projection: Option<ProjectionSettings>,
+    window_center: f32,
+    window_width: f32,
+    clip_minimum: [f32; 3],
+    clip_maximum: [f32; 3],
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct BookmarksFile {
+    bookmarks: Vec<Bookmark>,
+}
+
+// Camera bookmarks of a volume, stored in a json file next to it. Holding the save action
+// while pressing a bookmark action saves the current view in its slot, the bookmark action
+// alone moves back to it.
+pub struct Bookmarks {
+    path: PathBuf,
+    file: BookmarksFile,
+}
+
+impl Bookmarks {
+    // Bookmarks of `volume.bin` are in `volume.bookmarks.json`, next to the save
+    pub fn load(save_name: &str) -> Self {
+        let path = VolumeLoader::save_path(save_name).with_extension("bookmarks.json");
+        let file = match Self::read(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                println!("{}", error);
+                BookmarksFile::default()
+            }
+        };
+        Self { path, file }
+    }
+
+    fn read(path: &PathBuf) -> Result<BookmarksFile, BookmarksError> {
+        match read_to_string(path) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(BookmarksFile::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn save(&self) -> Result<(), BookmarksError> {
+        write(&self.path, serde_json::to_string_pretty(&self.file)?)?;
+        Ok(())
+    }
+
+    // Return the settings to apply when a bookmark is recalled
+    pub fn update(
+        &mut self,
+        input_manager: &mut InputManager,
+        player: &mut Player,
+        render_settings: &RenderSettings,
+    ) -> Option<RenderSettings> {
+        let save = input_manager.is_action_pressed(Action::SaveBookmark);
+        let mut recalled_settings = None;
+        for (index, action) in Action::BOOKMARKS.iter().enumerate() {
+            if !input_manager.is_action_pressed_once(*action) {
+                continue;
+            }
+            let slot = index + 1;
+            if save {
+                self.save_view(slot, player.view(), render_settings);
+            } else {
+                recalled_settings = self.recall(slot, player, render_settings);
+            }
+        }
+        recalled_settings
+    }
+
+    fn save_view(&mut self, slot: usize, view: CameraView, render_settings: &RenderSettings) {
+        // A renamed bookmark keeps its name
+        let name = self
+            .file
+            .bookmarks
+            .iter()
+            .find(|bookmark| bookmark.slot == slot)
+            .map(|bookmark| bookmark.name.clone())
+            .unwrap_or_else(|| format!("Bookmark {}", slot));
+        self.file.bookmarks.retain(|bookmark| bookmark.slot != slot);
+        self.file.bookmarks.push(Bookmark {
+            slot,
+            name,
+            position: view.position.into(),
+            rotation: view.rotation.into(),
+            up: view.up.into(),
+            orbit_target: view.orbit_target.map(Into::into),
+            orthographic: view.orthographic,
+            projection: Some(render_settings.camera_projection),
+            window_center: render_settings.window_center,
+            window_width: render_settings.window_width,
+            clip_minimum: render_settings.clip_minimum,
+            clip_maximum: render_settings.clip_maximum,
+        });
+        self.file.bookmarks.sort_by_key(|bookmark| bookmark.slot);
+
+        match self.save() {
+            Ok(()) => println!("Bookmark {} saved in {}", slot, self.path.display()),
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    fn recall(
+        &self,
+        slot: usize,
+        player: &mut Player,
+        render_settings: &RenderSettings,
+    ) -> Option<RenderSettings> {
+        let Some(bookmark) = self
+            .file
+            .bookmarks
+            .iter()
+            .find(|bookmark| bookmark.slot == slot)
+        else {
+            println!("No bookmark {}", slot);
+            return None;
+        };
+        println!("{}", bookmark.name);
+        player.move_to_view(CameraView {
+            position: Vector3::from(bookmark.position),
+            rotation: Vector2::from(bookmark.rotation),
+            up: Vector3::from(bookmark.up),
+            orbit_target: bookmark.orbit_target.map(Vector3::from),
+            orthographic: bookmark.orthographic,
+        });
+        Some(RenderSettings {
+            window_center: bookmark.window_center,
+            window_width: bookmark.window_width,
+            clip_minimum: bookmark.clip_minimum,
+            clip_maximum: bookmark.clip_maximum,
+            camera_projection: bookmark
+                .projection
+                .unwrap_or(render_settings.camera_projection),
+            ..render_settings.clone()
+        })
+    }
+}
@@ -1,6 +1,7 @@
 use cgmath::{
     ortho,
     perspective,
+    InnerSpace,
     Matrix4,
     Quaternion,
     Rad,
@@ -10,6 +11,94 @@ use cgmath::{
     Vector2,
     Vector3,
 };
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::io::assets::read_asset;
+
+const DEFAULT_ORTHO_HEIGHT: f32 = 2.0;
+// Field of view kept by the camera, in degrees. A perspective needs it strictly between 0
+// and 180.
+const MIN_FIELD_OF_VIEW: f32 = 1.0;
+const MAX_FIELD_OF_VIEW: f32 = 179.0;
+// Smallest near plane kept by the camera, the far plane stays at least this ratio further
+const MIN_NEAR_PLANE: f32 = 1e-6;
+const MIN_FAR_TO_NEAR_RATIO: f32 = 1.001;
+
+#[derive(Debug, Error)]
+pub enum ProjectionSettingsError
+{
+    #[error("Can't read camera settings: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Can't parse camera settings: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Invalid camera settings: {0}")]
+    InvalidValue(String),
+}
+
+// Parameters of the projection, read from the `camera.json` asset, replaced by the command
+// line and edited in the settings panel
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ProjectionSettings
+{
+    // Vertical field of view in degrees
+    pub field_of_view:    f32,
+    pub near_plane:       f32,
+    pub far_plane:        f32,
+    // Longest distance travelled by the rays, they stop earlier once past the whole volume
+    pub max_ray_distance: f32,
+}
+
+impl Default for ProjectionSettings
+{
+    fn default() -> Self
+    {
+        ProjectionSettings { field_of_view:    57.3,
+                             near_plane:       0.001,
+                             far_plane:        50.0,
+                             max_ray_distance: 50.0, }
+    }
+}
+
+impl ProjectionSettings
+{
+    pub fn load() -> Result<Self, ProjectionSettingsError>
+    {
+        Ok(serde_json::from_str(&read_asset("camera.json")?)?)
+    }
+
+    pub fn field_of_view(&self) -> Rad<f32>
+    {
+        Rad(self.field_of_view.to_radians())
+    }
+
+    // The perspective needs 0 < field of view < 180 degrees and 0 < near < far
+    pub fn validate(&self) -> Result<(), ProjectionSettingsError>
+    {
+        if !(self.field_of_view > 0.0 && self.field_of_view < 180.0)
+        {
+            return Err(ProjectionSettingsError::InvalidValue(format!("field of view must be between 0 and 180 degrees, not {}",
+                                                                     self.field_of_view)));
+        }
+        if !(self.near_plane > 0.0 && self.near_plane < self.far_plane)
+        {
+            return Err(ProjectionSettingsError::InvalidValue(format!("near plane ({}) must be positive and closer than the far plane ({})",
+                                                                     self.near_plane, self.far_plane)));
+        }
+        Ok(())
+    }
+
+    // Closest settings the perspective can be built with
+    pub fn clamped(&self) -> Self
+    {
+        let near_plane = self.near_plane.max(MIN_NEAR_PLANE);
+        ProjectionSettings { field_of_view: self.field_of_view.clamp(MIN_FIELD_OF_VIEW, MAX_FIELD_OF_VIEW),
+                             near_plane,
+                             far_plane: self.far_plane.max(near_plane * MIN_FAR_TO_NEAR_RATIO),
+                             ..*self }
+    }
+}
 
 #[derive(Debug)]
 pub struct Camera
@@ -21,33 +110,100 @@ pub struct Camera
     perspective_matrix:  Matrix4<f32>,
     previous_mvp_matrix: Matrix4<f32>,
     orthogonal_matrix:   Matrix4<f32>,
+    aspect_ratio:        f32,
+    projection:          ProjectionSettings,
+    // Vertical direction of the view, the yaw turns around it and the pitch goes towards it
+    up:                  Vector3<f32>,
+    // Parallel rays instead of rays from the camera position
+    orthographic:        bool,
+    // Height of the orthographic view in world units
+    ortho_height:        f32,
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone)]
 pub struct CameraUniform
 {
+    // Projection in use, perspective or orthographic
     pub mvp:                        [[f32; 4]; 4],
     pub previous_mvp:               [[f32; 4]; 4],
     pub orientation_inversed:       [[f32; 4]; 4],
-    pub mvp_ortho:                  [[f32; 4]; 4],
     pub orientation_inversed_ortho: [[f32; 4]; 4],
-    pub position:                   [f32; 4],
 }
 
 impl Camera
 {
     pub fn new(position: Vector3<f32>, aspect_ratio: f32) -> Camera
     {
-        Camera { position:            position,
-                 rotation:            Vector2 { x: 0.0, y: 0.0 },
-                 forward:             Vector3 { x: 0.0, y: 0.0, z: 1.0 },
-                 view_matrix:         Matrix4::look_at_rh(cgmath::Point3 { x: position.x, y: position.y, z: position.z },
-                                                          cgmath::Point3 { x: position.x + 0.0, y: position.y + 0.0, z: position.z + 1.0 },
-                                                          Vector3::new(0.0, 1.0, 0.0)),
-                 perspective_matrix:  perspective(Rad(1.0), aspect_ratio, 0.001, 50.0),
-                 previous_mvp_matrix: Matrix4::identity(),
-                 orthogonal_matrix:   ortho(-25.0, 25.0, -25.0, 25.0, 0.001, 50.0), }
+        let mut camera = Camera { position:            position,
+                                  rotation:            Vector2 { x: 0.0, y: 0.0 },
+                                  forward:             Vector3 { x: 0.0, y: 0.0, z: 1.0 },
+                                  view_matrix:         Matrix4::look_at_rh(cgmath::Point3 { x: position.x, y: position.y, z: position.z },
+                                                                           cgmath::Point3 { x: position.x + 0.0, y: position.y + 0.0, z: position.z + 1.0 },
+                                                                           Vector3::new(0.0, 1.0, 0.0)),
+                                  perspective_matrix:  Matrix4::identity(),
+                                  previous_mvp_matrix: Matrix4::identity(),
+                                  orthogonal_matrix:   Matrix4::identity(),
+                                  aspect_ratio,
+                                  projection:          ProjectionSettings::default(),
+                                  up:                  Vector3::unit_y(),
+                                  orthographic:        false,
+                                  ortho_height:        DEFAULT_ORTHO_HEIGHT, };
+        camera.update_projection_matrices();
+        camera
+    }
+
+    fn update_projection_matrices(&mut self)
+    {
+        let projection = self.projection;
+        self.perspective_matrix =
+            perspective(projection.field_of_view(), self.aspect_ratio, projection.near_plane, projection.far_plane);
+        let half_height = 0.5 * self.ortho_height;
+        let half_width = half_height * self.aspect_ratio;
+        self.orthogonal_matrix =
+            ortho(-half_width, half_width, -half_height, half_height, projection.near_plane, projection.far_plane);
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32)
+    {
+        self.aspect_ratio = aspect_ratio;
+        self.update_projection_matrices();
+    }
+
+    pub fn set_projection(&mut self, projection: ProjectionSettings)
+    {
+        let projection = projection.clamped();
+        if projection != self.projection
+        {
+            self.projection = projection;
+            self.update_projection_matrices();
+        }
+    }
+
+    pub fn get_projection(&self) -> &ProjectionSettings
+    {
+        &self.projection
+    }
+
+    pub fn set_orthographic(&mut self, orthographic: bool)
+    {
+        self.orthographic = orthographic;
+    }
+
+    pub fn is_orthographic(&self) -> bool
+    {
+        self.orthographic
+    }
+
+    pub fn set_ortho_height(&mut self, ortho_height: f32)
+    {
+        self.ortho_height = ortho_height;
+        self.update_projection_matrices();
+    }
+
+    // Size of the orthographic view in world units
+    pub fn get_ortho_size(&self) -> Vector2<f32>
+    {
+        Vector2::new(self.ortho_height * self.aspect_ratio, self.ortho_height)
     }
 
     pub fn set_position(&mut self, new_position: Vector3<f32>)
@@ -62,31 +218,73 @@ impl Camera
 
     pub fn rotate_forward(&mut self, rot: Vector2<f32>)
     {
-        self.rotation += rot;
+        self.set_rotation(self.rotation + rot);
+    }
 
-        if self.rotation.y > std::f32::consts::FRAC_PI_2 - 0.01
-        {
-            self.rotation.y = std::f32::consts::FRAC_PI_2 - 0.01
-        }
+    pub fn get_up(&self) -> Vector3<f32>
+    {
+        self.up
+    }
 
-        if self.rotation.y < -std::f32::consts::FRAC_PI_2 + 0.01
-        {
-            self.rotation.y = -std::f32::consts::FRAC_PI_2 + 0.01
-        }
+    // Change the vertical direction, the camera keeps looking the same way
+    pub fn set_up(&mut self, up: Vector3<f32>)
+    {
+        let forward = self.forward;
+        self.up = up.normalize();
+        self.look_towards(forward);
+    }
+
+    // Rotation from the frame where Y is up to the frame of the camera
+    fn up_frame(&self) -> Quaternion<f32>
+    {
+        Quaternion::from_arc(Vector3::unit_y(), self.up, Some(Vector3::unit_x()))
+    }
+
+    // Yaw (x) around the up direction and pitch (y) in radians, the pitch is kept away from
+    // the vertical
+    pub fn set_rotation(&mut self, rotation: Vector2<f32>)
+    {
+        const PITCH_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+        self.rotation = Vector2::new(rotation.x, rotation.y.clamp(-PITCH_LIMIT, PITCH_LIMIT));
 
         let rotation_quaternion_x: Quaternion<f32> = Rotation3::from_angle_x(Rad(self.rotation.y));
         let rotation_quaternion_y: Quaternion<f32> = Rotation3::from_angle_y(Rad(-self.rotation.x));
-        let rotation_quaternion = rotation_quaternion_y * rotation_quaternion_x;
+        let rotation_quaternion = self.up_frame() * rotation_quaternion_y * rotation_quaternion_x;
         self.forward = rotation_quaternion.rotate_vector(Vector3::unit_z());
     }
 
+    pub fn get_rotation(&self) -> Vector2<f32>
+    {
+        self.rotation
+    }
+
+    // Rotation giving a forward vector along `direction`
+    pub fn look_towards(&mut self, direction: Vector3<f32>)
+    {
+        let direction = self.up_frame().invert().rotate_vector(direction.normalize());
+        self.set_rotation(Vector2::new((-direction.x).atan2(direction.z), (-direction.y).asin()));
+    }
+
     pub fn recalculate_matrix(&mut self)
     {
-        self.previous_mvp_matrix = self.get_perspective_mvp_matrix();
+        self.previous_mvp_matrix = self.get_mvp_matrix();
         self.view_matrix =
             Matrix4::look_at_rh(cgmath::Point3 { x: self.position.x, y: self.position.y, z: self.position.z },
                                 cgmath::Point3 { x: self.position.x + self.forward.x, y: self.position.y + self.forward.y, z: self.position.z + self.forward.z },
-                                Vector3::new(0.0, 1.0, 0.0));
+                                self.up);
+    }
+
+    // Matrix of the projection in use
+    pub fn get_mvp_matrix(&self) -> Matrix4<f32>
+    {
+        if self.orthographic
+        {
+            self.get_ortho_mvp_matrix()
+        }
+        else
+        {
+            self.get_perspective_mvp_matrix()
+        }
     }
 
     pub fn get_perspective_mvp_matrix(&self) -> Matrix4<f32>
@@ -100,7 +298,7 @@ impl Camera
         self.perspective_matrix
         * Matrix4::look_at_rh(cgmath::Point3 { x: 0.0, y: 0.0, z: 0.0 },
                               cgmath::Point3 { x: self.forward.x, y: self.forward.y, z: self.forward.z },
-                              Vector3::new(0.0, 1.0, 0.0))
+                              self.up)
     }
 
     pub fn get_relative_ortho_mvp_matrix(&self) -> Matrix4<f32>
@@ -108,7 +306,7 @@ impl Camera
         self.orthogonal_matrix
         * Matrix4::look_at_rh(cgmath::Point3 { x: 0.0, y: 0.0, z: 0.0 },
                               cgmath::Point3 { x: self.forward.x, y: self.forward.y, z: self.forward.z },
-                              Vector3::new(0.0, 1.0, 0.0))
+                              self.up)
     }
 
     pub fn get_ortho_mvp_matrix(&self) -> Matrix4<f32>
@@ -127,18 +325,32 @@ impl Camera
         self.position
     }
 
-    pub fn _set_forward(&mut self, forward: Vector3<f32>)
-    {
-        self.forward = forward;
-    }
-
     pub fn get_uniform(&self) -> CameraUniform
     {
-        CameraUniform { mvp:                        self.get_perspective_mvp_matrix().into(),
+        CameraUniform { mvp:                        self.get_mvp_matrix().into(),
                         previous_mvp:               self.previous_mvp_matrix.into(),
                         orientation_inversed:       self.get_relative_persp_mvp_matrix().invert().unwrap().into(),
-                        mvp_ortho:                  self.get_ortho_mvp_matrix().into(),
-                        orientation_inversed_ortho: self.get_relative_ortho_mvp_matrix().invert().unwrap().into(),
-                        position:                   [self.position.x, self.position.y, self.position.z, 0.0], }
+                        orientation_inversed_ortho: self.get_relative_ortho_mvp_matrix().invert().unwrap().into(), }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn invalid_projections_are_rejected_and_clamped()
+    {
+        let default = ProjectionSettings::default();
+        assert!(default.validate().is_ok());
+        let wide = ProjectionSettings { field_of_view: 200.0, ..default };
+        let inverted = ProjectionSettings { near_plane: 60.0, ..default };
+        for projection in [wide, inverted]
+        {
+            assert!(projection.validate().is_err());
+            assert!(projection.clamped().validate().is_ok());
+        }
+        assert_eq!(default.clamped(), default);
     }
 }
@@ -1,2 +1,5 @@
+pub mod bookmarks;
 pub mod camera;
+pub mod orbit;
 pub mod player;
+pub mod view_preset;
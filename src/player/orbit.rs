@@ -0,0 +1,83 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+use crate::io::{bindings::Action, input_player::InputManager};
+
+use super::camera::Camera;
+
+const ROTATION_SENSIBILITY: f32 = 0.005;
+// Part of the distance to the target travelled per pixel when panning
+const PAN_SENSIBILITY: f32 = 0.0015;
+// Factor applied to the distance for each line scrolled
+const ZOOM_FACTOR: f32 = 0.9;
+const MIN_DISTANCE: f32 = 0.01;
+const MAX_DISTANCE: f32 = 20.0;
+
+// Camera turning around a target point: by default the left button (or the look mode)
+// rotates, the middle button pans and the wheel zooms
+pub struct OrbitCamera {
+    target: Vector3<f32>,
+    distance: f32,
+    rotation: Vector2<f32>,
+}
+
+impl OrbitCamera {
+    pub fn new(target: Vector3<f32>) -> Self {
+        Self {
+            target,
+            distance: 1.0,
+            rotation: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    pub fn target(&self) -> Vector3<f32> {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: Vector3<f32>) {
+        self.target = target;
+    }
+
+    // Look at the target from the current position of the camera
+    pub fn look_at_target(&mut self, camera: &mut Camera) {
+        let offset = self.target - camera.get_position();
+        self.distance = offset.magnitude().clamp(MIN_DISTANCE, MAX_DISTANCE);
+        if offset.magnitude2() > 0.0 {
+            camera.look_towards(offset);
+        }
+        self.rotation = camera.get_rotation();
+    }
+
+    // Turn around the target, in radians
+    pub fn rotate(&mut self, angles: Vector2<f32>) {
+        self.rotation += angles;
+    }
+
+    // Update the orientation of the camera and return its new position
+    pub fn update(
+        &mut self,
+        input_manager: &mut InputManager,
+        camera: &mut Camera,
+        look_active: bool,
+    ) -> Vector3<f32> {
+        let delta = input_manager.get_delta();
+        if look_active || input_manager.is_action_pressed(Action::OrbitRotate) {
+            self.rotation += delta * ROTATION_SENSIBILITY;
+        }
+        camera.set_rotation(self.rotation);
+        // The camera clamps the pitch
+        self.rotation = camera.get_rotation();
+        let forward = camera.get_forward();
+
+        if input_manager.is_action_pressed(Action::OrbitPan) {
+            let right = forward.cross(camera.get_up()).normalize();
+            let up = right.cross(forward);
+            self.target += (up * delta.y - right * delta.x) * PAN_SENSIBILITY * self.distance;
+        }
+
+        let zoom = input_manager.action_amount(Action::ZoomIn)
+            - input_manager.action_amount(Action::ZoomOut);
+        self.distance = (self.distance * ZOOM_FACTOR.powf(zoom)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+
+        self.target - forward * self.distance
+    }
+}
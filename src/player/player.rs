@@ -1,17 +1,102 @@
-use cgmath::{InnerSpace, Quaternion, Rad, Rotation, Rotation3, Vector3};
-use glutin::event::VirtualKeyCode;
+use cgmath::{InnerSpace, Vector2, Vector3};
 
-use crate::io::input_player::InputManager;
+use crate::io::{bindings::Action, input_player::InputManager};
 
-use super::camera::{Camera, CameraUniform};
+use super::{
+    camera::{Camera, CameraUniform, ProjectionSettings},
+    orbit::OrbitCamera,
+    view_preset::{default_view_direction, ViewPreset},
+};
 
 const EYES_HEIGHT: f32 = 0.20;
 const MOUSE_SENSIBILITY: f32 = 0.003;
-const SPEED: f32 = 0.2;
+// Radians per second with an analog look axis fully pushed
+const STICK_LOOK_SPEED: f32 = 2.0;
+// Speed multiplier of the move faster action
+const FAST_MULTIPLIER: f32 = 5.0;
+// Factor applied to the speed for each line scrolled in first person
+const SPEED_STEP: f32 = 1.2;
+const MIN_SPEED_SCALE: f32 = 0.05;
+const MAX_SPEED_SCALE: f32 = 20.0;
+// Longer frames are simulated as this time step, so a stall does not throw the camera away
+const MAX_TIME_STEP: f32 = 0.1;
+// Space left around the volume when it is framed
+const FRAMING_MARGIN: f32 = 1.1;
+// The camera stays in a sphere around the volume, whose radius is this factor times the
+// framing distance
+const ROAMING_RADIUS_FACTOR: f32 = 3.0;
+// Size used for the speed before a volume is loaded
+const MIN_VOLUME_SIZE: f32 = 0.1;
+// Smallest distance to the orbit target used for the size of the orthographic view
+const MIN_FOCUS_DISTANCE: f32 = 0.01;
+// Duration in seconds of the move to a bookmarked view
+const TRANSITION_DURATION: f32 = 0.8;
+
+// Motion of the first person camera, edited in the settings panel
+#[derive(Clone, Copy, PartialEq)]
+pub struct MotionSettings {
+    // Volume diagonals travelled per second, without the fast multiplier
+    pub speed: f32,
+    // Rate per second at which the velocity reaches the one asked by the movement actions
+    pub acceleration: f32,
+    // Rate per second at which the velocity falls to zero without movement action
+    pub damping: f32,
+    // Delay in seconds of the rotation behind the mouse, 0 turns directly with it
+    pub mouse_smoothing: f32,
+}
+
+impl MotionSettings {
+    pub fn new() -> Self {
+        Self {
+            speed: 0.1,
+            acceleration: 10.0,
+            damping: 8.0,
+            mouse_smoothing: 0.02,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CameraMode {
+    // Fly camera moved with the keyboard and turned with the mouse
+    FirstPerson,
+    // Camera turning around the orbit target
+    Orbit,
+}
+
+// Placement of the camera, saved in the bookmarks
+#[derive(Clone, Copy)]
+pub struct CameraView {
+    pub position: Vector3<f32>,
+    pub rotation: Vector2<f32>,
+    // Vertical direction of the camera, the rotation is measured around it
+    pub up: Vector3<f32>,
+    // Target of the orbit camera, None for the first person camera
+    pub orbit_target: Option<Vector3<f32>>,
+    pub orthographic: bool,
+}
+
+// Move from the current view to a bookmarked one
+struct Transition {
+    from: CameraView,
+    to: CameraView,
+    elapsed: f32,
+}
 
 pub struct Player {
     player_position: Vector3<f32>,
     pub camera: Camera,
+    motion: MotionSettings,
+    // Multiplier of the speed changed with the wheel
+    speed_scale: f32,
+    velocity: Vector3<f32>,
+    // Radians per second of the smoothed rotation
+    look_velocity: Vector2<f32>,
+    mode: CameraMode,
+    orbit: OrbitCamera,
+    // Box framed by the reset and preset views
+    volume_bounds: (Vector3<f32>, Vector3<f32>),
+    transition: Option<Transition>,
 }
 
 impl Player {
@@ -22,18 +107,92 @@ impl Player {
                 spawn_position + Vector3::new(0.0, EYES_HEIGHT, 0.0),
                 aspect_ratio,
             ),
+            motion: MotionSettings::new(),
+            speed_scale: 1.0,
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            look_velocity: Vector2::new(0.0, 0.0),
+            mode: CameraMode::FirstPerson,
+            orbit: OrbitCamera::new(spawn_position),
+            volume_bounds: (spawn_position, spawn_position),
+            transition: None,
         }
     }
 
-    pub fn update(&mut self, input_manager: &mut InputManager, time_since_last_update: f32) {
-        // Move player and head using keyboard/mouse input
-        self.update_movement_body(input_manager, time_since_last_update);
-        self.update_movement_head(input_manager);
+    // The mouse turns the camera only in look mode
+    pub fn update(
+        &mut self,
+        input_manager: &mut InputManager,
+        time_since_last_update: f32,
+        look_active: bool,
+    ) {
+        let time_since_last_update = time_since_last_update.min(MAX_TIME_STEP);
+
+        // Switch between the first person and the orbit camera
+        if input_manager.is_action_pressed_once(Action::ToggleOrbit) {
+            self.toggle_mode();
+        }
+
+        if input_manager.is_action_pressed_once(Action::ToggleOrthographic) {
+            let orthographic = !self.camera.is_orthographic();
+            self.camera.set_orthographic(orthographic);
+            println!(
+                "{} projection",
+                if orthographic {
+                    "Orthographic"
+                } else {
+                    "Perspective"
+                }
+            );
+        }
+
+        // Frame the volume again or from one of the anatomical views
+        if input_manager.is_action_pressed_once(Action::ResetView) {
+            self.reset_view();
+        }
+        for view_preset in ViewPreset::ALL {
+            if input_manager.is_action_pressed_once(view_preset.action()) {
+                self.frame_volume(view_preset.direction(), view_preset.up());
+            }
+        }
+
+        // The camera follows the move to a bookmarked view until it is reached
+        if self.transition.is_some() {
+            self.update_transition(time_since_last_update);
+        } else {
+            match self.mode {
+                CameraMode::FirstPerson => {
+                    // Move player and head using keyboard/mouse/gamepad input
+                    self.update_movement_body(input_manager, time_since_last_update);
+                    self.update_movement_head(input_manager, time_since_last_update, look_active);
+                    self.clamp_to_roaming_region();
+                }
+                CameraMode::Orbit => {
+                    self.orbit
+                        .rotate(Self::stick_look(input_manager) * time_since_last_update);
+                    let eye_position =
+                        self.orbit
+                            .update(input_manager, &mut self.camera, look_active);
+                    self.player_position = eye_position - self.eye_offset();
+                }
+            }
+        }
         input_manager.reset_delta();
 
         // Set camera position according to the position of player
         self.camera
-            .set_position(self.player_position + Vector3::new(0.0, EYES_HEIGHT, 0.0));
+            .set_position(self.player_position + self.eye_offset());
+        self.update_ortho_height();
+    }
+
+    // The orthographic view shows the orbit target at the same size as the perspective
+    // view, so moving towards it or zooming still magnifies the volume
+    fn update_ortho_height(&mut self) {
+        let focus_distance = (self.orbit.target() - self.camera.get_position())
+            .dot(self.camera.get_forward())
+            .max(MIN_FOCUS_DISTANCE);
+        let field_of_view = self.camera.get_projection().field_of_view().0;
+        self.camera
+            .set_ortho_height(2.0 * focus_distance * (0.5 * field_of_view).tan());
     }
 
     fn update_movement_body(
@@ -41,95 +200,234 @@ impl Player {
         input_manager: &mut InputManager,
         time_since_last_update: f32,
     ) {
-        // Move camera using WASD inputs
+        // The wheel changes the speed
+        let speed_change = input_manager.action_amount(Action::SpeedUp)
+            - input_manager.action_amount(Action::SpeedDown);
+        self.speed_scale = (self.speed_scale * SPEED_STEP.powf(speed_change))
+            .clamp(MIN_SPEED_SCALE, MAX_SPEED_SCALE);
+
+        // Velocity asked by the movement actions, a partly pushed stick moves slower than
+        // a key
         let mut multiplicator = 1.0;
-        if input_manager.is_pressed(VirtualKeyCode::LShift) {
-            multiplicator = 5.0;
+        if input_manager.is_action_pressed(Action::MoveFaster) {
+            multiplicator = FAST_MULTIPLIER;
         }
+        let (forward_vector, left_vector) = self.horizontal_directions();
+        let direction = forward_vector
+            * (input_manager.action_amount(Action::MoveForward)
+                - input_manager.action_amount(Action::MoveBackward))
+            + left_vector
+                * (input_manager.action_amount(Action::MoveLeft)
+                    - input_manager.action_amount(Action::MoveRight))
+            + self.camera.get_up()
+                * (input_manager.action_amount(Action::MoveUp)
+                    - input_manager.action_amount(Action::MoveDown));
+        let target_velocity = direction * self.speed() * multiplicator;
 
-        if input_manager.is_pressed(VirtualKeyCode::Space) {
-            self.move_up(time_since_last_update * multiplicator);
-        }
-        if input_manager.is_pressed(VirtualKeyCode::LControl) {
-            self.move_down(time_since_last_update * multiplicator);
-        }
-        if input_manager.is_pressed(VirtualKeyCode::Z) {
-            self.move_forward(time_since_last_update * multiplicator);
+        // Accelerate towards the asked velocity, or slow down without movement action
+        let rate = if direction.magnitude2() > 0.0 {
+            self.motion.acceleration
+        } else {
+            self.motion.damping
+        };
+        self.velocity +=
+            (target_velocity - self.velocity) * smoothing(rate, time_since_last_update);
+        self.player_position += self.velocity * time_since_last_update;
+    }
+
+    fn update_movement_head(
+        &mut self,
+        input_manager: &mut InputManager,
+        time_since_last_update: f32,
+        look_active: bool,
+    ) {
+        // Move camera using mouse input information in look mode and the look axes
+        let mut rotation = Self::stick_look(input_manager) * time_since_last_update;
+        if look_active {
+            rotation += input_manager.get_delta() * MOUSE_SENSIBILITY;
         }
-        if input_manager.is_pressed(VirtualKeyCode::S) {
-            self.move_backward(time_since_last_update * multiplicator);
+        if self.motion.mouse_smoothing > 0.0 && time_since_last_update > 0.0 {
+            self.look_velocity += (rotation / time_since_last_update - self.look_velocity)
+                * smoothing(1.0 / self.motion.mouse_smoothing, time_since_last_update);
+            rotation = self.look_velocity * time_since_last_update;
+        } else {
+            self.look_velocity = Vector2::new(0.0, 0.0);
         }
-        if input_manager.is_pressed(VirtualKeyCode::Q) {
-            self.move_left(time_since_last_update * multiplicator);
+        self.camera.rotate_forward(rotation);
+    }
+
+    // Rotation speed given by the analog look actions, in radians per second
+    fn stick_look(input_manager: &mut InputManager) -> Vector2<f32> {
+        Vector2::new(
+            input_manager.action_amount(Action::LookRight)
+                - input_manager.action_amount(Action::LookLeft),
+            input_manager.action_amount(Action::LookDown)
+                - input_manager.action_amount(Action::LookUp),
+        ) * STICK_LOOK_SPEED
+    }
+
+    // Forward and left directions of the camera in the plane orthogonal to its up direction
+    fn horizontal_directions(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let up = self.camera.get_up();
+        let forward = self.camera.get_forward();
+        let horizontal_forward = (forward - up * forward.dot(up)).normalize();
+        let left_vector = up.cross(horizontal_forward);
+        (horizontal_forward, left_vector)
+    }
+
+    // Offset from the player to the camera, along the up direction of the camera
+    fn eye_offset(&self) -> Vector3<f32> {
+        self.camera.get_up() * EYES_HEIGHT
+    }
+
+    // Distance travelled per second, proportional to the size of the volume
+    fn speed(&self) -> f32 {
+        let (minimum, maximum) = self.volume_bounds;
+        let volume_size = (maximum - minimum).magnitude().max(MIN_VOLUME_SIZE);
+        self.motion.speed * self.speed_scale * volume_size
+    }
+
+    // Keep the camera in a sphere around the volume, so it can't get lost far away
+    fn clamp_to_roaming_region(&mut self) {
+        let (center, radius) = self.bounding_sphere();
+        let maximum_distance = ROAMING_RADIUS_FACTOR * self.framing_distance(radius);
+        let eye_position = self.player_position + self.eye_offset();
+        let offset = eye_position - center;
+        if offset.magnitude() <= maximum_distance {
+            return;
         }
-        if input_manager.is_pressed(VirtualKeyCode::D) {
-            self.move_right(time_since_last_update * multiplicator);
+        let outward = offset.normalize();
+        self.player_position = center + outward * maximum_distance - self.eye_offset();
+        // Stop moving away from the volume
+        self.velocity -= outward * self.velocity.dot(outward).max(0.0);
+    }
+
+    fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraMode::FirstPerson => {
+                self.orbit.look_at_target(&mut self.camera);
+                println!("Orbit camera");
+                CameraMode::Orbit
+            }
+            CameraMode::Orbit => {
+                self.velocity = Vector3::new(0.0, 0.0, 0.0);
+                println!("First person camera");
+                CameraMode::FirstPerson
+            }
+        };
+    }
+
+    pub fn view(&self) -> CameraView {
+        CameraView {
+            position: self.camera.get_position(),
+            rotation: self.camera.get_rotation(),
+            up: self.camera.get_up(),
+            orbit_target: match self.mode {
+                CameraMode::FirstPerson => None,
+                CameraMode::Orbit => Some(self.orbit.target()),
+            },
+            orthographic: self.camera.is_orthographic(),
         }
     }
 
-    fn update_movement_head(&mut self, input_manager: &InputManager) {
-        // Move camera using mouse input information
-        self.camera
-            .rotate_forward(input_manager.get_delta() * MOUSE_SENSIBILITY);
+    // Move smoothly to a view, the camera mode is switched once it is reached
+    pub fn move_to_view(&mut self, view: CameraView) {
+        self.velocity = Vector3::new(0.0, 0.0, 0.0);
+        self.camera.set_orthographic(view.orthographic);
+        // Both rotations are measured around the up direction of the target view
+        self.camera.set_up(view.up);
+        self.transition = Some(Transition {
+            from: self.view(),
+            to: view,
+            elapsed: 0.0,
+        });
     }
 
-    fn move_forward(&mut self, time_since_last_update: f32) {
-        let mut forward_without_y = Vector3::new(
-            self.camera.get_forward().x,
-            0.0,
-            self.camera.get_forward().z,
-        );
-        forward_without_y = InnerSpace::normalize(forward_without_y);
+    fn update_transition(&mut self, time_since_last_update: f32) {
+        let Some(transition) = self.transition.as_mut() else {
+            return;
+        };
+        transition.elapsed += time_since_last_update;
+        let progress = (transition.elapsed / TRANSITION_DURATION).min(1.0);
+        // Ease in and out
+        let weight = progress * progress * (3.0 - 2.0 * progress);
+        let (from, to) = (transition.from, transition.to);
 
-        let movement_vector_x = Vector3::new(
-            SPEED * forward_without_y.x * time_since_last_update,
-            0.0,
-            0.0,
-        );
-        let movement_vector_z = Vector3::new(
-            0.0,
-            0.0,
-            SPEED * forward_without_y.z * time_since_last_update,
+        // Turn the shortest way around
+        let mut yaw_difference = (to.rotation.x - from.rotation.x) % std::f32::consts::TAU;
+        if yaw_difference > std::f32::consts::PI {
+            yaw_difference -= std::f32::consts::TAU;
+        } else if yaw_difference < -std::f32::consts::PI {
+            yaw_difference += std::f32::consts::TAU;
+        }
+        let rotation = Vector2::new(
+            from.rotation.x + weight * yaw_difference,
+            from.rotation.y + weight * (to.rotation.y - from.rotation.y),
         );
+        let position = from.position + (to.position - from.position) * weight;
+        self.camera.set_rotation(rotation);
+        self.player_position = position - self.eye_offset();
 
-        self.player_position += movement_vector_x;
-        self.player_position += movement_vector_z;
+        if progress >= 1.0 {
+            self.transition = None;
+            self.camera.set_position(to.position);
+            match to.orbit_target {
+                Some(orbit_target) => {
+                    self.orbit.set_target(orbit_target);
+                    self.orbit.look_at_target(&mut self.camera);
+                    self.mode = CameraMode::Orbit;
+                }
+                None => self.mode = CameraMode::FirstPerson,
+            }
+        }
     }
 
-    fn move_backward(&mut self, time_since_last_update: f32) {
-        self.move_forward(-time_since_last_update);
+    // Box of the visible voxels, its center is the orbit target
+    pub fn set_volume_bounds(&mut self, minimum: Vector3<f32>, maximum: Vector3<f32>) {
+        self.volume_bounds = (minimum, maximum);
+        self.orbit.set_target(0.5 * (minimum + maximum));
     }
 
-    fn move_left(&mut self, time_since_last_update: f32) {
-        let mut forward_without_y = Vector3::new(
-            self.camera.get_forward().x,
-            0.0,
-            self.camera.get_forward().z,
-        );
-        forward_without_y = InnerSpace::normalize(forward_without_y);
-        let rotation_left: Quaternion<f32> =
-            Rotation3::from_angle_y(Rad(std::f32::consts::FRAC_PI_2));
-        let left_vector = rotation_left.rotate_vector(forward_without_y);
+    pub fn reset_view(&mut self) {
+        self.frame_volume(default_view_direction(), Vector3::unit_y());
+    }
 
-        let movement_vector_x =
-            Vector3::new(SPEED * left_vector.x * time_since_last_update, 0.0, 0.0);
-        let movement_vector_z =
-            Vector3::new(0.0, 0.0, SPEED * left_vector.z * time_since_last_update);
+    // Look at the whole volume along `direction` with `up` at the top of the screen, from the
+    // distance where its bounding sphere fits in the field of view
+    pub fn frame_volume(&mut self, direction: Vector3<f32>, up: Vector3<f32>) {
+        self.camera.set_up(up);
+        let (center, radius) = self.bounding_sphere();
+        let distance = self.framing_distance(radius);
+
+        let eye_position = center - direction.normalize() * distance;
+        self.velocity = Vector3::new(0.0, 0.0, 0.0);
+        self.transition = None;
+        self.player_position = eye_position - self.eye_offset();
+        self.camera.set_position(eye_position);
+        self.orbit.set_target(center);
+        self.orbit.look_at_target(&mut self.camera);
+    }
 
-        self.player_position += movement_vector_x;
-        self.player_position += movement_vector_z;
+    fn bounding_sphere(&self) -> (Vector3<f32>, f32) {
+        let (minimum, maximum) = self.volume_bounds;
+        (
+            0.5 * (minimum + maximum),
+            0.5 * (maximum - minimum).magnitude(),
+        )
     }
 
-    fn move_right(&mut self, time_since_last_update: f32) {
-        self.move_left(-time_since_last_update);
+    // Distance where a sphere fits in the field of view
+    fn framing_distance(&self, radius: f32) -> f32 {
+        let field_of_view = self.camera.get_projection().field_of_view().0;
+        FRAMING_MARGIN * radius / (0.5 * field_of_view).sin()
     }
 
-    fn move_up(&mut self, time_since_last_update: f32) {
-        self.player_position += SPEED * time_since_last_update * Vector3::unit_y()
+    pub fn set_motion_settings(&mut self, motion: MotionSettings) {
+        self.motion = motion;
     }
 
-    fn move_down(&mut self, time_since_last_update: f32) {
-        self.move_up(-time_since_last_update);
+    pub fn set_projection_settings(&mut self, projection: ProjectionSettings) {
+        self.camera.set_projection(projection);
     }
 
     pub fn _get_position(&self) -> Vector3<f32> {
@@ -142,11 +440,16 @@ impl Player {
 
     pub fn _teleport(&mut self, position: Vector3<f32>) {
         self.player_position = position;
-        self.camera
-            .set_position(position + Vector3::new(0.0, EYES_HEIGHT, 0.0))
+        self.camera.set_position(position + self.eye_offset())
     }
 
     pub fn get_uniform(&self) -> CameraUniform {
         self.camera.get_uniform()
     }
 }
+
+// Part of the remaining distance to a target covered during `time` when approaching it at
+// `rate` per second, the same whatever the frame rate
+fn smoothing(rate: f32, time: f32) -> f32 {
+    1.0 - (-rate * time).exp()
+}
@@ -0,0 +1,88 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::io::bindings::Action;
+
+// The volume axes are assumed to point to the patient's left, posterior and superior sides
+// (DICOM). Labels of the positive and negative side of the x, y and z axes.
+pub const PATIENT_AXES: [(&str, &str); 3] = [("L", "R"), ("P", "A"), ("S", "I")];
+
+// Standard anatomical views, along the axes of `PATIENT_AXES`. The camera looks at the patient
+// from the named side.
+#[derive(Clone, Copy, Debug)]
+pub enum ViewPreset {
+    Anterior,
+    Posterior,
+    Left,
+    Right,
+    Superior,
+    Inferior,
+}
+
+impl ViewPreset {
+    pub const ALL: [ViewPreset; 6] = [
+        ViewPreset::Anterior,
+        ViewPreset::Posterior,
+        ViewPreset::Left,
+        ViewPreset::Right,
+        ViewPreset::Superior,
+        ViewPreset::Inferior,
+    ];
+
+    pub fn action(&self) -> Action {
+        match self {
+            ViewPreset::Anterior => Action::ViewAnterior,
+            ViewPreset::Posterior => Action::ViewPosterior,
+            ViewPreset::Left => Action::ViewLeft,
+            ViewPreset::Right => Action::ViewRight,
+            ViewPreset::Superior => Action::ViewSuperior,
+            ViewPreset::Inferior => Action::ViewInferior,
+        }
+    }
+
+    // Direction the camera looks along
+    pub fn direction(&self) -> Vector3<f32> {
+        match self {
+            ViewPreset::Anterior => Vector3::unit_y(),
+            ViewPreset::Posterior => -Vector3::unit_y(),
+            ViewPreset::Left => -Vector3::unit_x(),
+            ViewPreset::Right => Vector3::unit_x(),
+            ViewPreset::Superior => -Vector3::unit_z(),
+            ViewPreset::Inferior => Vector3::unit_z(),
+        }
+    }
+
+    // Direction shown at the top of the screen: superior for the views from the sides,
+    // anterior for the views from above and below
+    pub fn up(&self) -> Vector3<f32> {
+        match self {
+            ViewPreset::Superior | ViewPreset::Inferior => -Vector3::unit_y(),
+            _ => Vector3::unit_z(),
+        }
+    }
+}
+
+// Direction of the view restored by the reset key: from the inferior side and slightly
+// above, like the camera placed when the application starts
+pub fn default_view_direction() -> Vector3<f32> {
+    Vector3::new(0.0, -0.3, 1.0).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::camera::Camera;
+
+    #[test]
+    fn presets_look_along_their_direction_with_their_up() {
+        let mut camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), 1.0);
+        for preset in ViewPreset::ALL {
+            camera.set_up(preset.up());
+            camera.look_towards(preset.direction());
+            assert!((camera.get_forward() - preset.direction()).magnitude() < 1e-4);
+            assert!((camera.get_up() - preset.up()).magnitude() < 1e-4);
+            // The screen up seen by the view matrix is the preset up
+            let right = camera.get_forward().cross(camera.get_up());
+            assert!(right.magnitude() > 0.99);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use gl::types::GLuint;
+
+use super::voxel_buffer::{VoxelBuffer, BYTES_PER_VOXEL};
+
+// Number of voxels along one side of a brick
+pub const BRICK_SIZE: usize = 8;
+// Number of bricks along one side of a macro cell, the coarser level used to skip large
+// empty regions in one step
+pub const MACRO_CELL_SIZE: usize = 4;
+
+// Statistics of one brick (or macro cell), used to skip empty space while raymarching
+#[derive(Clone, Copy, Debug)]
+pub struct BrickInfo {
+    // Range of the intensities of the visible voxels, minimum above maximum if there is none
+    pub min_intensity: u8,
+    pub max_intensity: u8,
+}
+
+impl BrickInfo {
+    fn empty() -> Self {
+        Self {
+            min_intensity: u8::MAX,
+            max_intensity: 0,
+        }
+    }
+
+    fn merge(&mut self, other: &BrickInfo) {
+        self.min_intensity = self.min_intensity.min(other.min_intensity);
+        self.max_intensity = self.max_intensity.max(other.max_intensity);
+    }
+}
+
+// Partition of a voxel buffer in cubic bricks of BRICK_SIZE voxels, grouped in macro cells of
+// MACRO_CELL_SIZE bricks
+pub struct BrickGrid {
+    bricks_per_axis: usize,
+    bricks: Vec<BrickInfo>,
+    macro_cells_per_axis: usize,
+    macro_cells: Vec<BrickInfo>,
+}
+
+// Intensity range of each brick and macro cell, read by the raymarching to skip the ones
+// with no visible voxel
+pub struct OccupancyTexture {
+    pub texture_id: GLuint,
+    pub macro_texture_id: GLuint,
+}
+
+impl BrickGrid {
+    pub fn new(voxels: &VoxelBuffer) -> Self {
+        let bricks_per_axis = voxels.size().div_ceil(BRICK_SIZE);
+        let macro_cells_per_axis = bricks_per_axis.div_ceil(MACRO_CELL_SIZE);
+        let mut brick_grid = Self {
+            bricks_per_axis,
+            bricks: vec![BrickInfo::empty(); bricks_per_axis.pow(3)],
+            macro_cells_per_axis,
+            macro_cells: vec![BrickInfo::empty(); macro_cells_per_axis.pow(3)],
+        };
+        brick_grid.rebuild(voxels);
+        brick_grid
+    }
+
+    // Recompute statistics of every brick in a single pass over the buffer, then of the
+    // macro cells from the bricks
+    pub fn rebuild(&mut self, voxels: &VoxelBuffer) {
+        self.bricks.fill(BrickInfo::empty());
+
+        let size = voxels.size();
+        let bytes = voxels.as_bytes();
+        for z in 0..size {
+            for y in 0..size {
+                let row_offset = BYTES_PER_VOXEL * size * (y + size * z);
+                let row_brick = self.bricks_per_axis
+                    * (y / BRICK_SIZE + self.bricks_per_axis * (z / BRICK_SIZE));
+                for x in 0..size {
+                    let voxel = &bytes[row_offset + BYTES_PER_VOXEL * x..][..BYTES_PER_VOXEL];
+                    // Transparent voxels are not rendered
+                    if voxel[3] == 0 {
+                        continue;
+                    }
+                    let brick = &mut self.bricks[row_brick + x / BRICK_SIZE];
+                    brick.min_intensity = brick.min_intensity.min(voxel[0]);
+                    brick.max_intensity = brick.max_intensity.max(voxel[0]);
+                }
+            }
+        }
+
+        self.macro_cells.fill(BrickInfo::empty());
+        let bricks_per_axis = self.bricks_per_axis;
+        for (index, brick) in self.bricks.iter().enumerate() {
+            let x = index % bricks_per_axis / MACRO_CELL_SIZE;
+            let y = index / bricks_per_axis % bricks_per_axis / MACRO_CELL_SIZE;
+            let z = index / (bricks_per_axis * bricks_per_axis) / MACRO_CELL_SIZE;
+            self.macro_cells[x + self.macro_cells_per_axis * (y + self.macro_cells_per_axis * z)]
+                .merge(brick);
+        }
+    }
+
+    pub fn bricks_per_axis(&self) -> usize {
+        self.bricks_per_axis
+    }
+
+    pub fn macro_cells_per_axis(&self) -> usize {
+        self.macro_cells_per_axis
+    }
+
+    // Two bytes per brick: minimum and maximum intensity of its visible voxels
+    pub fn intensity_ranges(&self) -> Vec<u8> {
+        Self::ranges(&self.bricks)
+    }
+
+    pub fn macro_intensity_ranges(&self) -> Vec<u8> {
+        Self::ranges(&self.macro_cells)
+    }
+
+    fn ranges(cells: &[BrickInfo]) -> Vec<u8> {
+        cells
+            .iter()
+            .flat_map(|cell| [cell.min_intensity, cell.max_intensity])
+            .collect()
+    }
+}
+
+impl OccupancyTexture {
+    pub unsafe fn new() -> Self {
+        Self {
+            texture_id: Self::create_texture(),
+            macro_texture_id: Self::create_texture(),
+        }
+    }
+
+    unsafe fn create_texture() -> GLuint {
+        let mut texture_id: GLuint = 0;
+        gl::GenTextures(1, &mut texture_id);
+        gl::BindTexture(gl::TEXTURE_3D, texture_id);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        texture_id
+    }
+
+    pub unsafe fn regenerate_texture(&self, brick_grid: &BrickGrid) {
+        Self::upload(
+            self.texture_id,
+            brick_grid.bricks_per_axis(),
+            &brick_grid.intensity_ranges(),
+        );
+        Self::upload(
+            self.macro_texture_id,
+            brick_grid.macro_cells_per_axis(),
+            &brick_grid.macro_intensity_ranges(),
+        );
+    }
+
+    unsafe fn upload(texture_id: GLuint, size: usize, ranges: &[u8]) {
+        let size = size as i32;
+        gl::BindTexture(gl::TEXTURE_3D, texture_id);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage3D(
+            gl::TEXTURE_3D,
+            0,
+            gl::RG8 as i32,
+            size,
+            size,
+            size,
+            0,
+            gl::RG,
+            gl::UNSIGNED_BYTE,
+            ranges.as_ptr() as *const std::ffi::c_void,
+        );
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Vector4};
+
+    use super::*;
+    use crate::world::world_data::Bloc;
+
+    fn bloc(value: u8) -> Bloc {
+        Bloc {
+            color: Vector4::new(value, value, value, 255),
+        }
+    }
+
+    #[test]
+    fn macro_cells_merge_the_ranges_of_their_bricks() {
+        let size = 2 * MACRO_CELL_SIZE * BRICK_SIZE;
+        let mut voxels = VoxelBuffer::new(size);
+        voxels.set(Vector3::new(0, 0, 0), bloc(40));
+        voxels.set(Vector3::new(BRICK_SIZE as i32, 0, 0), bloc(200));
+        voxels.set(Vector3::new(size as i32 - 1, 0, 0), bloc(90));
+        let bricks = BrickGrid::new(&voxels);
+
+        assert_eq!(bricks.macro_cells_per_axis(), 2);
+        assert_eq!(&bricks.intensity_ranges()[..4], &[40, 40, 200, 200]);
+        // Empty cells have their minimum above their maximum
+        assert_eq!(
+            bricks.macro_intensity_ranges(),
+            [[40, 200], [90, 90]]
+                .into_iter()
+                .chain(std::iter::repeat_n([u8::MAX, 0], 6))
+                .flatten()
+                .collect::<Vec<u8>>()
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+use cgmath::{Vector2, Vector3, Vector4};
+
+use super::{
+    brick::BrickGrid,
+    lod::LodPyramid,
+    paging::{BrickSource, PREVIEW_LOD_LEVEL},
+    save_header::SaveHeader,
+    voxel_buffer::{VoxelBuffer, BYTES_PER_VOXEL},
+    world_data::{Bloc, WORLD_SIZE},
+};
+
+// Size of the chunks read from disk between two progress reports
+const READ_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+// Volume decoded by the loader thread, ready to be uploaded by the GL thread
+pub enum LoadedVolume {
+    InMemory {
+        voxel_size: Vector2<f32>,
+        spacing: Vector2<f32>,
+        dimensions: Vector3<usize>,
+        voxels: VoxelBuffer,
+        bricks: BrickGrid,
+        lod: LodPyramid,
+    },
+    Streamed {
+        source: BrickSource,
+        lod: LodPyramid,
+    },
+}
+
+pub enum LoadStatus {
+    Loading { progress_changed: bool },
+    Finished(LoadedVolume),
+    Failed(String),
+}
+
+enum LoadEvent {
+    Progress(f32),
+    Finished(LoadedVolume),
+    Failed(String),
+}
+
+// Read and decode a save on a worker thread so the window stays responsive
+pub struct VolumeLoader {
+    receiver: Receiver<LoadEvent>,
+    progress: f32,
+}
+
+impl VolumeLoader {
+    pub fn start(save_name: &str, stream_volume: bool) -> Self {
+        let (sender, receiver) = channel();
+        let path = Self::save_path(save_name);
+        thread::spawn(move || {
+            let result = if stream_volume {
+                Self::open_streamed(&path, &sender)
+            } else {
+                Self::load_in_memory(&path, &sender)
+            };
+            // The receiver is gone if the window was closed during loading
+            let _ = match result {
+                Ok(volume) => sender.send(LoadEvent::Finished(volume)),
+                Err(error) => {
+                    sender.send(LoadEvent::Failed(format!("{}: {}", path.display(), error)))
+                }
+            };
+        });
+
+        Self {
+            receiver,
+            progress: 0.0,
+        }
+    }
+
+    // A save is given either by its path or by its name in the `saves` directory
+    pub fn save_path(save_name: &str) -> PathBuf {
+        if Path::new(save_name).is_file() {
+            PathBuf::from(save_name)
+        } else {
+            PathBuf::from(format!("saves/{}.bin", save_name))
+        }
+    }
+
+    // Progress between 0 and 1
+    pub fn progress(&self) -> f32 {
+        self.progress
+    }
+
+    // Handle messages of the worker since the last call
+    pub fn poll(&mut self) -> LoadStatus {
+        let mut progress_changed = false;
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                LoadEvent::Progress(progress) => {
+                    self.progress = progress;
+                    progress_changed = true;
+                }
+                LoadEvent::Finished(volume) => return LoadStatus::Finished(volume),
+                LoadEvent::Failed(error) => return LoadStatus::Failed(error),
+            }
+        }
+        LoadStatus::Loading { progress_changed }
+    }
+
+    fn load_in_memory(path: &Path, sender: &Sender<LoadEvent>) -> std::io::Result<LoadedVolume> {
+        // Reading the file: first half of the progress bar
+        let mut file = File::open(path)?;
+        let file_size = file.metadata()?.len() as usize;
+        let mut flatten = vec![0u8; file_size];
+        let mut read_bytes = 0;
+        while read_bytes < file_size {
+            let chunk_end = (read_bytes + READ_CHUNK_SIZE).min(file_size);
+            file.read_exact(&mut flatten[read_bytes..chunk_end])?;
+            read_bytes = chunk_end;
+            let _ = sender.send(LoadEvent::Progress(
+                0.5 * read_bytes as f32 / file_size as f32,
+            ));
+        }
+
+        let header = SaveHeader::parse(&flatten)?;
+        let voxel_size = header.voxel_size;
+        let world_size = header.dimensions;
+        // Voxels outside of the world texture are dropped, larger volumes must be streamed
+        if world_size.x > WORLD_SIZE || world_size.y > WORLD_SIZE || world_size.z > WORLD_SIZE {
+            println!(
+                "Volume is larger than {} voxels per axis, it is cropped (use --stream to see it whole)",
+                WORLD_SIZE
+            );
+        }
+
+        // Converting to the texture layout: up to 90%
+        let mut voxels = VoxelBuffer::new(WORLD_SIZE);
+        let mut i = 0;
+        for x in 0..world_size.x {
+            for y in 0..world_size.y {
+                for z in 0..world_size.z {
+                    let offset = header.size + BYTES_PER_VOXEL * i;
+                    voxels.set(
+                        Vector3::new(x as i32, y as i32, z as i32),
+                        Bloc {
+                            color: Vector4::new(
+                                flatten[offset],
+                                flatten[offset + 1],
+                                flatten[offset + 2],
+                                flatten[offset + 3],
+                            ),
+                        },
+                    );
+                    i += 1;
+                }
+            }
+            let _ = sender.send(LoadEvent::Progress(
+                0.5 + 0.4 * (x + 1) as f32 / world_size.x as f32,
+            ));
+        }
+        drop(flatten);
+
+        let bricks = BrickGrid::new(&voxels);
+        let lod = LodPyramid::new(&voxels);
+        let _ = sender.send(LoadEvent::Progress(1.0));
+
+        Ok(LoadedVolume::InMemory {
+            voxel_size,
+            spacing: header.spacing,
+            dimensions: world_size.map(|size| size.min(WORLD_SIZE)),
+            voxels,
+            bricks,
+            lod,
+        })
+    }
+
+    fn open_streamed(path: &Path, sender: &Sender<LoadEvent>) -> std::io::Result<LoadedVolume> {
+        let source = BrickSource::open(path)?;
+        let preview = source.read_downsampled(PREVIEW_LOD_LEVEL, &mut |progress| {
+            let _ = sender.send(LoadEvent::Progress(0.9 * progress));
+        });
+        let lod = LodPyramid::from_level(PREVIEW_LOD_LEVEL, preview);
+        let _ = sender.send(LoadEvent::Progress(1.0));
+
+        Ok(LoadedVolume::Streamed { source, lod })
+    }
+}
@@ -0,0 +1,184 @@
+use super::voxel_buffer::{VoxelBuffer, BYTES_PER_VOXEL};
+
+// Number of levels of detail, level 0 being the full resolution volume
+pub const LOD_LEVELS: usize = 4;
+// Distance (in voxels) from the camera under which the full resolution is used.
+// Each time the distance doubles, the raymarcher uses the next level.
+pub const LOD_DISTANCE_IN_VOXELS: f32 = 64.0;
+
+// Downsampled copies of a volume, each level being half the size of the previous one.
+// Level 0 is not stored here: it is the volume itself (or the bricks of a streamed volume).
+pub struct LodPyramid {
+    first_level: usize,
+    levels: Vec<VoxelBuffer>,
+}
+
+impl LodPyramid {
+    // Build levels 1 to LOD_LEVELS - 1 from the full resolution volume
+    pub fn new(voxels: &VoxelBuffer) -> Self {
+        let first_level = downsample(voxels);
+        Self::from_level(1, first_level)
+    }
+
+    // Pyramid of an empty volume of `size` voxels per axis
+    pub fn empty(size: usize) -> Self {
+        let levels = (1..LOD_LEVELS)
+            .map(|level| VoxelBuffer::new(level_size(size, level)))
+            .collect();
+        Self {
+            first_level: 1,
+            levels,
+        }
+    }
+
+    // Build the pyramid from an already downsampled level (used when the full
+    // resolution volume is never loaded in memory)
+    pub fn from_level(first_level: usize, voxels: VoxelBuffer) -> Self {
+        let mut levels = vec![voxels];
+        for _ in first_level + 1..LOD_LEVELS {
+            let next_level = downsample(levels.last().unwrap());
+            levels.push(next_level);
+        }
+        Self {
+            first_level,
+            levels,
+        }
+    }
+
+    pub fn first_level(&self) -> usize {
+        self.first_level
+    }
+
+    pub fn last_level(&self) -> usize {
+        self.first_level + self.levels.len() - 1
+    }
+
+    // Iterate over (level, voxels) from the finest to the coarsest level
+    pub fn levels(&self) -> impl Iterator<Item = (usize, &VoxelBuffer)> {
+        self.levels
+            .iter()
+            .enumerate()
+            .map(|(index, voxels)| (self.first_level + index, voxels))
+    }
+}
+
+// Size of a level of detail, following the mipmap sizes of GL so the levels can be uploaded
+// as mipmaps of one texture
+fn level_size(size: usize, level: usize) -> usize {
+    (size >> level).max(1)
+}
+
+// Halve the resolution of a volume: colors are averaged over the visible voxels of each
+// 2x2x2 block, alpha (the label of the voxel) keeps the maximum. The size is rounded down,
+// so on an odd size the last block of each axis also covers the last voxel.
+pub fn downsample(voxels: &VoxelBuffer) -> VoxelBuffer {
+    let size = voxels.size();
+    let half_size = level_size(size, 1);
+    let mut downsampled = VoxelBuffer::new(half_size);
+    let source = voxels.as_bytes();
+    let destination = downsampled.as_bytes_mut();
+    // Voxels of the volume covered by a voxel of the downsampled level on one axis
+    let covered = |index: usize| {
+        let end = if index + 1 == half_size {
+            size
+        } else {
+            2 * index + 2
+        };
+        2 * index..end
+    };
+
+    for z in 0..half_size {
+        for y in 0..half_size {
+            for x in 0..half_size {
+                let mut color_sum = [0u32; 3];
+                let mut visible_voxels = 0;
+                let mut alpha = 0;
+                for sz in covered(z) {
+                    for sy in covered(y) {
+                        for sx in covered(x) {
+                            let offset = BYTES_PER_VOXEL * (sx + size * (sy + size * sz));
+                            let voxel = &source[offset..offset + BYTES_PER_VOXEL];
+                            if voxel[3] == 0 {
+                                continue;
+                            }
+                            for channel in 0..3 {
+                                color_sum[channel] += voxel[channel] as u32;
+                            }
+                            visible_voxels += 1;
+                            alpha = alpha.max(voxel[3]);
+                        }
+                    }
+                }
+                if visible_voxels == 0 {
+                    continue;
+                }
+                let offset = BYTES_PER_VOXEL * (x + half_size * (y + half_size * z));
+                for channel in 0..3 {
+                    destination[offset + channel] = (color_sum[channel] / visible_voxels) as u8;
+                }
+                destination[offset + 3] = alpha;
+            }
+        }
+    }
+    downsampled
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Vector4};
+
+    use super::*;
+    use crate::world::world_data::Bloc;
+
+    fn voxel(voxels: &VoxelBuffer, x: usize, y: usize, z: usize) -> [u8; 4] {
+        let size = voxels.size();
+        let offset = BYTES_PER_VOXEL * (x + size * (y + size * z));
+        voxels.as_bytes()[offset..offset + BYTES_PER_VOXEL]
+            .try_into()
+            .unwrap()
+    }
+
+    fn set(voxels: &mut VoxelBuffer, x: i32, y: i32, z: i32, color: [u8; 4]) {
+        voxels.set(
+            Vector3::new(x, y, z),
+            Bloc {
+                color: Vector4::from(color),
+            },
+        );
+    }
+
+    #[test]
+    fn level_sizes_follow_gl_mipmaps() {
+        assert_eq!(downsample(&VoxelBuffer::new(64)).size(), 32);
+        assert_eq!(downsample(&VoxelBuffer::new(5)).size(), 2);
+        assert_eq!(downsample(&VoxelBuffer::new(1)).size(), 1);
+        let empty = LodPyramid::empty(100);
+        let sizes: Vec<usize> = empty.levels().map(|(_, level)| level.size()).collect();
+        assert_eq!(sizes, vec![50, 25, 12]);
+        let built = LodPyramid::new(&VoxelBuffer::new(100));
+        let built_sizes: Vec<usize> = built.levels().map(|(_, level)| level.size()).collect();
+        assert_eq!(built_sizes, sizes);
+    }
+
+    #[test]
+    fn downsample_averages_visible_voxels() {
+        let mut voxels = VoxelBuffer::new(4);
+        set(&mut voxels, 0, 0, 0, [10, 20, 30, 1]);
+        set(&mut voxels, 1, 1, 1, [30, 40, 50, 7]);
+        // Transparent voxels don't darken the average
+        set(&mut voxels, 1, 0, 0, [200, 200, 200, 0]);
+        let downsampled = downsample(&voxels);
+        assert_eq!(voxel(&downsampled, 0, 0, 0), [20, 30, 40, 7]);
+        assert_eq!(voxel(&downsampled, 1, 0, 0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn downsample_keeps_the_last_voxel_of_odd_sizes() {
+        let mut voxels = VoxelBuffer::new(5);
+        set(&mut voxels, 4, 4, 4, [90, 90, 90, 3]);
+        set(&mut voxels, 2, 3, 4, [30, 30, 30, 2]);
+        let downsampled = downsample(&voxels);
+        assert_eq!(voxel(&downsampled, 1, 1, 1), [60, 60, 60, 3]);
+        assert_eq!(voxel(&downsampled, 0, 0, 0), [0, 0, 0, 0]);
+    }
+}
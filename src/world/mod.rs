@@ -1 +1,7 @@
+pub mod brick;
+pub mod loader;
+pub mod lod;
+pub mod paging;
+pub mod save_header;
+pub mod voxel_buffer;
 pub mod world_data;
@@ -0,0 +1,701 @@
+use std::{fs::File, path::Path};
+
+use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+use gl::types::GLuint;
+use memmap2::Mmap;
+
+use super::{
+    brick::BRICK_SIZE,
+    save_header::SaveHeader,
+    voxel_buffer::{VoxelBuffer, BYTES_PER_VOXEL},
+};
+
+// Number of bricks along one side of the atlas texture kept on the GPU
+pub const ATLAS_BRICKS_PER_AXIS: usize = 24;
+// Level of detail of the low resolution copy kept in memory while bricks are streamed
+pub const PREVIEW_LOD_LEVEL: usize = 2;
+// Maximum number of bricks read from disk and uploaded each frame
+const MAX_BRICK_UPLOADS_PER_FRAME: usize = 64;
+// Bricks along each side of the groups tested against the view before their bricks
+const BRICK_GROUP_SIZE: usize = 8;
+
+// Alpha of the indirection texture: 0 if the brick is not streamed, EMPTY_BRICK_FLAG if
+// it is known to be empty, 255 if it is resident in the atlas
+const EMPTY_BRICK_FLAG: u8 = 128;
+
+const BRICK_VOXELS: usize = BRICK_SIZE * BRICK_SIZE * BRICK_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BrickState {
+    // Never read from disk (or evicted since)
+    NotLoaded,
+    // Read from disk and found without any visible voxel: never needs a slot
+    Empty,
+    // Stored in the atlas at the given slot
+    Resident(usize),
+}
+
+// Volume save file mapped in memory, read brick by brick
+pub struct BrickSource {
+    mmap: Mmap,
+    pub header: SaveHeader,
+}
+
+// Volume too large to be uploaded at once: bricks are streamed into a fixed size
+// atlas according to what the camera sees, and an indirection texture tells the
+// shaders where each brick lives in the atlas
+pub struct PagedVolume {
+    source: BrickSource,
+    bricks_per_axis: usize,
+    states: Vec<BrickState>,
+    slot_owners: Vec<Option<usize>>,
+    slot_last_used: Vec<u64>,
+    // Resident slots from the most to the least recently used
+    slot_queue: SlotQueue,
+    free_slots: Vec<usize>,
+    indirection: Vec<u8>,
+    // Bricks whose entry of the indirection texture changed since the last upload
+    dirty_bricks: Vec<usize>,
+    frame: u64,
+    // View of the last update, and whether bricks it sees are still missing
+    last_view: Option<(Matrix4<f32>, f32)>,
+    missing_bricks: bool,
+    atlas_texture_id: GLuint,
+    indirection_texture_id: GLuint,
+}
+
+impl BrickSource {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        let header = SaveHeader::parse(&mmap).map_err(|error| {
+            std::io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+        })?;
+        Ok(Self { mmap, header })
+    }
+
+    // Bricks along each side of the grid holding the volume, the grid is cubic like the
+    // textures sampled by the shaders
+    pub fn bricks_per_axis(&self) -> usize {
+        let dimensions = self.header.dimensions;
+        dimensions
+            .x
+            .max(dimensions.y)
+            .max(dimensions.z)
+            .div_ceil(BRICK_SIZE)
+            .max(1)
+    }
+
+    // Voxels along each side of the grid
+    pub fn grid_size(&self) -> usize {
+        self.bricks_per_axis() * BRICK_SIZE
+    }
+
+    // Copy the voxels of one brick in `brick_buffer` (x varying fastest).
+    // Voxels outside of the saved volume are transparent.
+    // Returns true if at least one voxel is visible.
+    fn read_brick(&self, brick: Vector3<usize>, brick_buffer: &mut [u8]) -> bool {
+        brick_buffer.fill(0);
+        let mut is_occupied = false;
+        let origin = brick * BRICK_SIZE;
+        let dimensions = self.header.dimensions;
+        for z in 0..BRICK_SIZE {
+            for y in 0..BRICK_SIZE {
+                for x in 0..BRICK_SIZE {
+                    let voxel = origin + Vector3::new(x, y, z);
+                    if voxel.x >= dimensions.x || voxel.y >= dimensions.y || voxel.z >= dimensions.z
+                    {
+                        continue;
+                    }
+                    // Saves are written with z varying fastest
+                    let offset = self.header.voxel_offset(voxel);
+                    let destination =
+                        BYTES_PER_VOXEL * (x + BRICK_SIZE * y + BRICK_SIZE * BRICK_SIZE * z);
+                    brick_buffer[destination..destination + BYTES_PER_VOXEL]
+                        .copy_from_slice(&self.mmap[offset..offset + BYTES_PER_VOXEL]);
+                    is_occupied |= self.mmap[offset + 3] > 0;
+                }
+            }
+        }
+        is_occupied
+    }
+
+    // Low resolution copy of the whole save (each voxel covers 2^level voxels per axis),
+    // shown in place of the bricks which are not streamed yet.
+    // Colors are averaged over visible voxels, alpha keeps the maximum.
+    pub fn read_downsampled(&self, level: usize, on_progress: &mut dyn FnMut(f32)) -> VoxelBuffer {
+        let size = self.grid_size() >> level;
+        let mut color_sums = vec![[0u32; 4]; size * size * size];
+        let mut downsampled = VoxelBuffer::new(size);
+        let destination = downsampled.as_bytes_mut();
+
+        let dimensions = self.header.dimensions;
+        let mut offset = self.header.size;
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                for z in 0..dimensions.z {
+                    let voxel = &self.mmap[offset..offset + BYTES_PER_VOXEL];
+                    offset += BYTES_PER_VOXEL;
+                    if voxel[3] == 0 {
+                        continue;
+                    }
+                    let index = (x >> level) + size * ((y >> level) + size * (z >> level));
+                    for channel in 0..3 {
+                        color_sums[index][channel] += voxel[channel] as u32;
+                    }
+                    color_sums[index][3] += 1;
+                    let alpha = &mut destination[BYTES_PER_VOXEL * index + 3];
+                    *alpha = (*alpha).max(voxel[3]);
+                }
+            }
+            on_progress((x + 1) as f32 / dimensions.x as f32);
+        }
+
+        for (index, color_sum) in color_sums.iter().enumerate() {
+            if color_sum[3] == 0 {
+                continue;
+            }
+            for channel in 0..3 {
+                destination[BYTES_PER_VOXEL * index + channel] =
+                    (color_sum[channel] / color_sum[3]) as u8;
+            }
+        }
+        downsampled
+    }
+}
+
+impl PagedVolume {
+    pub unsafe fn new(source: BrickSource) -> Self {
+        let bricks_per_axis = source.bricks_per_axis();
+        let slot_count = ATLAS_BRICKS_PER_AXIS.pow(3);
+        let atlas_size = (ATLAS_BRICKS_PER_AXIS * BRICK_SIZE) as i32;
+
+        let mut atlas_texture_id: GLuint = 0;
+        gl::GenTextures(1, &mut atlas_texture_id);
+        gl::BindTexture(gl::TEXTURE_3D, atlas_texture_id);
+        Self::set_nearest_parameters();
+        gl::TexImage3D(
+            gl::TEXTURE_3D,
+            0,
+            gl::RGBA8 as i32,
+            atlas_size,
+            atlas_size,
+            atlas_size,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null(),
+        );
+
+        // Allocated once, then only the entries of the bricks which change are sent
+        let indirection = vec![0u8; 4 * bricks_per_axis.pow(3)];
+        let mut indirection_texture_id: GLuint = 0;
+        gl::GenTextures(1, &mut indirection_texture_id);
+        gl::BindTexture(gl::TEXTURE_3D, indirection_texture_id);
+        Self::set_nearest_parameters();
+        gl::TexImage3D(
+            gl::TEXTURE_3D,
+            0,
+            gl::RGBA8 as i32,
+            bricks_per_axis as i32,
+            bricks_per_axis as i32,
+            bricks_per_axis as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            indirection.as_ptr() as *const std::ffi::c_void,
+        );
+
+        Self {
+            source,
+            bricks_per_axis,
+            states: vec![BrickState::NotLoaded; bricks_per_axis.pow(3)],
+            slot_owners: vec![None; slot_count],
+            slot_last_used: vec![0; slot_count],
+            slot_queue: SlotQueue::new(slot_count),
+            free_slots: (0..slot_count).rev().collect(),
+            indirection,
+            dirty_bricks: Vec::new(),
+            frame: 0,
+            last_view: None,
+            missing_bricks: false,
+            atlas_texture_id,
+            indirection_texture_id,
+        }
+    }
+
+    unsafe fn set_nearest_parameters() {
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+    }
+
+    pub fn bricks_per_axis(&self) -> usize {
+        self.bricks_per_axis
+    }
+
+    fn brick_index(&self, brick: Vector3<usize>) -> usize {
+        brick.x + self.bricks_per_axis * (brick.y + self.bricks_per_axis * brick.z)
+    }
+
+    // Size of a brick in world units
+    fn brick_extent(&self) -> Vector3<f32> {
+        let voxel_size = self.source.header.voxel_size;
+        Vector3::new(voxel_size.x, voxel_size.x, voxel_size.y) * BRICK_SIZE as f32
+    }
+
+    // Bricks inside the view frustum which overlap the saved volume and are closer than
+    // `max_distance`, closest first
+    fn visible_bricks(
+        &self,
+        camera_position: Vector3<f32>,
+        mvp: Matrix4<f32>,
+        max_distance: f32,
+    ) -> Vec<usize> {
+        let brick_counts = self
+            .source
+            .header
+            .dimensions
+            .map(|size| size.div_ceil(BRICK_SIZE).min(self.bricks_per_axis));
+        bricks_in_view(
+            brick_counts,
+            self.brick_extent(),
+            camera_position,
+            mvp,
+            max_distance,
+        )
+        .into_iter()
+        .map(|brick| self.brick_index(brick))
+        .collect()
+    }
+
+    // Free the least recently used slot that was not needed during this frame
+    fn evict_slot(&mut self) -> Option<usize> {
+        let slot = self.slot_queue.least_recently_used()?;
+        if self.slot_last_used[slot] >= self.frame {
+            return None;
+        }
+        self.slot_queue.remove(slot);
+        let owner = self.slot_owners[slot].take().unwrap();
+        self.states[owner] = BrickState::NotLoaded;
+        self.indirection[4 * owner..4 * owner + 4].fill(0);
+        self.dirty_bricks.push(owner);
+        Some(slot)
+    }
+
+    fn touch_slot(&mut self, slot: usize) {
+        self.slot_last_used[slot] = self.frame;
+        self.slot_queue.move_to_front(slot);
+    }
+
+    // Request the bricks seen from the camera and upload the missing ones
+    pub unsafe fn update(
+        &mut self,
+        camera_position: Vector3<f32>,
+        mvp: Matrix4<f32>,
+        max_distance: f32,
+    ) {
+        // Nothing changes until the camera moves, unless bricks are still waiting
+        let view = (mvp, max_distance);
+        if self.last_view == Some(view) && !self.missing_bricks {
+            return;
+        }
+        self.last_view = Some(view);
+        self.missing_bricks = false;
+
+        self.frame += 1;
+        let mut brick_buffer = vec![0u8; BYTES_PER_VOXEL * BRICK_VOXELS];
+        let mut uploads = 0;
+
+        // Bricks already resident must not be evicted to make room for the others
+        let visible_bricks = self.visible_bricks(camera_position, mvp, max_distance);
+        for index in visible_bricks.iter() {
+            if let BrickState::Resident(slot) = self.states[*index] {
+                self.touch_slot(slot);
+            }
+        }
+
+        for index in visible_bricks {
+            if self.states[index] == BrickState::NotLoaded {
+                if uploads >= MAX_BRICK_UPLOADS_PER_FRAME {
+                    self.missing_bricks = true;
+                    break;
+                }
+                let brick = Vector3::new(
+                    index % self.bricks_per_axis,
+                    (index / self.bricks_per_axis) % self.bricks_per_axis,
+                    index / (self.bricks_per_axis * self.bricks_per_axis),
+                );
+                if !self.source.read_brick(brick, &mut brick_buffer) {
+                    self.states[index] = BrickState::Empty;
+                    self.indirection[4 * index + 3] = EMPTY_BRICK_FLAG;
+                    self.dirty_bricks.push(index);
+                    continue;
+                }
+                let slot = match self.free_slots.pop().or_else(|| self.evict_slot()) {
+                    Some(slot) => slot,
+                    // Atlas is full of bricks needed by this frame
+                    None => {
+                        self.missing_bricks = true;
+                        continue;
+                    }
+                };
+                self.upload_brick(slot, &brick_buffer);
+                self.states[index] = BrickState::Resident(slot);
+                self.slot_owners[slot] = Some(index);
+                self.touch_slot(slot);
+                self.indirection[4 * index..4 * index + 4]
+                    .copy_from_slice(&Self::slot_coordinates(slot));
+                self.dirty_bricks.push(index);
+                uploads += 1;
+            }
+        }
+
+        self.upload_indirection();
+    }
+
+    // Position of a slot in the atlas (in bricks) followed by the "resident" flag
+    fn slot_coordinates(slot: usize) -> [u8; 4] {
+        [
+            (slot % ATLAS_BRICKS_PER_AXIS) as u8,
+            ((slot / ATLAS_BRICKS_PER_AXIS) % ATLAS_BRICKS_PER_AXIS) as u8,
+            (slot / (ATLAS_BRICKS_PER_AXIS * ATLAS_BRICKS_PER_AXIS)) as u8,
+            255,
+        ]
+    }
+
+    unsafe fn upload_brick(&self, slot: usize, brick_buffer: &[u8]) {
+        let coordinates = Self::slot_coordinates(slot);
+        gl::BindTexture(gl::TEXTURE_3D, self.atlas_texture_id);
+        gl::TexSubImage3D(
+            gl::TEXTURE_3D,
+            0,
+            (coordinates[0] as usize * BRICK_SIZE) as i32,
+            (coordinates[1] as usize * BRICK_SIZE) as i32,
+            (coordinates[2] as usize * BRICK_SIZE) as i32,
+            BRICK_SIZE as i32,
+            BRICK_SIZE as i32,
+            BRICK_SIZE as i32,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            brick_buffer.as_ptr() as *const std::ffi::c_void,
+        );
+    }
+
+    // Send the changed entries of the indirection texture, one upload for each run of
+    // changed bricks along x
+    unsafe fn upload_indirection(&mut self) {
+        if self.dirty_bricks.is_empty() {
+            return;
+        }
+        self.dirty_bricks.sort_unstable();
+        self.dirty_bricks.dedup();
+        gl::BindTexture(gl::TEXTURE_3D, self.indirection_texture_id);
+        let row_length = self.bricks_per_axis;
+        let mut runs = self.dirty_bricks.iter().peekable();
+        while let Some(&first) = runs.next() {
+            let row = first / row_length;
+            let mut last = first;
+            while let Some(&&next) = runs.peek() {
+                if next / row_length != row {
+                    break;
+                }
+                last = next;
+                runs.next();
+            }
+            gl::TexSubImage3D(
+                gl::TEXTURE_3D,
+                0,
+                (first % row_length) as i32,
+                (row % row_length) as i32,
+                (row / row_length) as i32,
+                (last - first + 1) as i32,
+                1,
+                1,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                self.indirection[4 * first..].as_ptr() as *const std::ffi::c_void,
+            );
+        }
+        self.dirty_bricks.clear();
+    }
+
+    pub fn atlas_texture_id(&self) -> GLuint {
+        self.atlas_texture_id
+    }
+
+    pub fn indirection_texture_id(&self) -> GLuint {
+        self.indirection_texture_id
+    }
+}
+
+// Planes bounding the volume seen through `mvp`, as (normal, offset) with the normal pointing
+// inside and scaled to unit length, so the value of a plane at a point is its distance
+fn frustum_planes(mvp: Matrix4<f32>) -> [Vector4<f32>; 6] {
+    let row = |index: usize| Vector4::new(mvp.x[index], mvp.y[index], mvp.z[index], mvp.w[index]);
+    let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+    [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| plane / plane.truncate().magnitude())
+}
+
+// Bricks of a grid of `brick_counts` bricks of `brick_extent` inside the view frustum of
+// `mvp` and closer than `max_distance` to the camera, closest first. Only the bricks in reach
+// of the camera are considered, and groups of bricks outside of the view are skipped at once.
+fn bricks_in_view(
+    brick_counts: Vector3<usize>,
+    brick_extent: Vector3<f32>,
+    camera_position: Vector3<f32>,
+    mvp: Matrix4<f32>,
+    max_distance: f32,
+) -> Vec<Vector3<usize>> {
+    let frustum_planes = frustum_planes(mvp);
+    // A sphere of `radius` around `center` may be seen
+    let in_view = |center: Vector3<f32>, radius: f32| {
+        (center - camera_position).magnitude() <= max_distance + radius
+            && frustum_planes
+                .iter()
+                .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    };
+    // Box (in bricks, [min, max)) of `count` bricks within `max_distance` of the camera
+    // on one axis
+    let reach = |camera: f32, extent: f32, count: usize| {
+        let min = ((camera - max_distance) / extent).floor().max(0.0);
+        let max = ((camera + max_distance) / extent).ceil().max(0.0);
+        (
+            min.min(count as f32) as usize,
+            max.min(count as f32) as usize,
+        )
+    };
+    let ranges = [
+        reach(camera_position.x, brick_extent.x, brick_counts.x),
+        reach(camera_position.y, brick_extent.y, brick_counts.y),
+        reach(camera_position.z, brick_extent.z, brick_counts.z),
+    ];
+    let center = |min: Vector3<usize>, max: Vector3<usize>| {
+        Vector3::new(
+            0.5 * (min.x + max.x) as f32 * brick_extent.x,
+            0.5 * (min.y + max.y) as f32 * brick_extent.y,
+            0.5 * (min.z + max.z) as f32 * brick_extent.z,
+        )
+    };
+    let radius = |bricks: usize| 0.5 * bricks as f32 * brick_extent.magnitude();
+
+    let mut visible_bricks: Vec<(f32, Vector3<usize>)> = Vec::new();
+    let groups = |(min, max): (usize, usize)| {
+        (min / BRICK_GROUP_SIZE..max.div_ceil(BRICK_GROUP_SIZE)).map(move |group| {
+            (
+                (group * BRICK_GROUP_SIZE).max(min),
+                ((group + 1) * BRICK_GROUP_SIZE).min(max),
+            )
+        })
+    };
+    for (z_min, z_max) in groups(ranges[2]) {
+        for (y_min, y_max) in groups(ranges[1]) {
+            for (x_min, x_max) in groups(ranges[0]) {
+                let group_min = Vector3::new(x_min, y_min, z_min);
+                let group_max = Vector3::new(x_max, y_max, z_max);
+                if !in_view(center(group_min, group_max), radius(BRICK_GROUP_SIZE)) {
+                    continue;
+                }
+                for z in z_min..z_max {
+                    for y in y_min..y_max {
+                        for x in x_min..x_max {
+                            let brick = Vector3::new(x, y, z);
+                            let brick_center = center(brick, brick + Vector3::new(1, 1, 1));
+                            if in_view(brick_center, radius(1)) {
+                                let distance = (brick_center - camera_position).magnitude();
+                                visible_bricks.push((distance, brick));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    visible_bricks.sort_by(|a, b| a.0.total_cmp(&b.0));
+    visible_bricks.into_iter().map(|(_, brick)| brick).collect()
+}
+
+// Doubly linked list of the resident slots of the atlas, ordered by last use
+struct SlotQueue {
+    previous: Vec<Option<usize>>,
+    next: Vec<Option<usize>>,
+    // Most recently used slot
+    front: Option<usize>,
+    // Least recently used slot
+    back: Option<usize>,
+    queued: Vec<bool>,
+}
+
+impl SlotQueue {
+    fn new(slot_count: usize) -> Self {
+        Self {
+            previous: vec![None; slot_count],
+            next: vec![None; slot_count],
+            front: None,
+            back: None,
+            queued: vec![false; slot_count],
+        }
+    }
+
+    fn least_recently_used(&self) -> Option<usize> {
+        self.back
+    }
+
+    fn remove(&mut self, slot: usize) {
+        if !self.queued[slot] {
+            return;
+        }
+        match self.previous[slot] {
+            Some(previous) => self.next[previous] = self.next[slot],
+            None => self.front = self.next[slot],
+        }
+        match self.next[slot] {
+            Some(next) => self.previous[next] = self.previous[slot],
+            None => self.back = self.previous[slot],
+        }
+        self.previous[slot] = None;
+        self.next[slot] = None;
+        self.queued[slot] = false;
+    }
+
+    fn move_to_front(&mut self, slot: usize) {
+        self.remove(slot);
+        self.next[slot] = self.front;
+        match self.front {
+            Some(front) => self.previous[front] = Some(slot),
+            None => self.back = Some(slot),
+        }
+        self.front = Some(slot);
+        self.queued[slot] = true;
+    }
+}
+
+impl Drop for PagedVolume {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, [self.atlas_texture_id].as_ptr());
+            gl::DeleteTextures(1, [self.indirection_texture_id].as_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{remove_file, write};
+
+    use cgmath::{perspective, Deg, ElementWise, EuclideanSpace, Point3};
+
+    use super::*;
+
+    // Save whose voxels hold their own coordinates, written with z varying fastest
+    fn write_save(name: &str, dimensions: Vector3<usize>) -> std::path::PathBuf {
+        let mut bytes = b"BOLV".to_vec();
+        bytes.extend_from_slice(&[10, 0, 5]);
+        for size in [dimensions.x, dimensions.y, dimensions.z] {
+            bytes.extend_from_slice(&(size as u32).to_le_bytes());
+        }
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                for z in 0..dimensions.z {
+                    bytes.extend_from_slice(&[x as u8, y as u8, z as u8, 255]);
+                }
+            }
+        }
+        let path = std::env::temp_dir().join(format!("bol_{}_{}.bin", name, std::process::id()));
+        write(&path, bytes).unwrap();
+        path
+    }
+
+    fn brick_voxel(brick_buffer: &[u8], x: usize, y: usize, z: usize) -> &[u8] {
+        let offset = BYTES_PER_VOXEL * (x + BRICK_SIZE * (y + BRICK_SIZE * z));
+        &brick_buffer[offset..offset + BYTES_PER_VOXEL]
+    }
+
+    #[test]
+    fn slot_queue_evicts_the_least_recently_used_slot() {
+        let mut queue = SlotQueue::new(4);
+        assert_eq!(queue.least_recently_used(), None);
+        for slot in [2, 0, 3] {
+            queue.move_to_front(slot);
+        }
+        assert_eq!(queue.least_recently_used(), Some(2));
+        queue.move_to_front(2);
+        assert_eq!(queue.least_recently_used(), Some(0));
+        queue.remove(0);
+        assert_eq!(queue.least_recently_used(), Some(3));
+        queue.remove(3);
+        queue.remove(2);
+        assert_eq!(queue.least_recently_used(), None);
+    }
+
+    #[test]
+    fn bricks_in_view_match_a_test_of_every_brick() {
+        let brick_counts = Vector3::new(40, 30, 20);
+        let brick_extent = Vector3::new(0.08, 0.08, 0.04);
+        let camera_position = Vector3::new(1.0, 1.5, -0.5);
+        let target = Vector3::new(1.6, 1.0, 0.4);
+        let view = Matrix4::look_at_rh(
+            Point3::from_vec(camera_position),
+            Point3::from_vec(target),
+            Vector3::unit_y(),
+        );
+        let mvp = perspective(Deg(60.0), 1.5, 0.01, 10.0) * view;
+        let max_distance = 1.5;
+
+        let mut expected = Vec::new();
+        let planes = frustum_planes(mvp);
+        let radius = 0.5 * brick_extent.magnitude();
+        for z in 0..brick_counts.z {
+            for y in 0..brick_counts.y {
+                for x in 0..brick_counts.x {
+                    let center = (Vector3::new(x, y, z).cast::<f32>().unwrap()
+                        + Vector3::new(0.5, 0.5, 0.5))
+                    .mul_element_wise(brick_extent);
+                    let visible = (center - camera_position).magnitude() <= max_distance + radius
+                        && planes
+                            .iter()
+                            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius);
+                    if visible {
+                        expected.push(Vector3::new(x, y, z));
+                    }
+                }
+            }
+        }
+        let mut found = bricks_in_view(
+            brick_counts,
+            brick_extent,
+            camera_position,
+            mvp,
+            max_distance,
+        );
+        assert!(!found.is_empty());
+        let key = |brick: &Vector3<usize>| (brick.z, brick.y, brick.x);
+        found.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn read_brick_reorders_z_fastest_voxels() {
+        let dimensions = Vector3::new(3, 11, 5);
+        let path = write_save("read_brick", dimensions);
+        let source = BrickSource::open(&path).unwrap();
+        remove_file(&path).unwrap();
+        assert_eq!(source.bricks_per_axis(), 2);
+
+        let mut brick_buffer = vec![0u8; BYTES_PER_VOXEL * BRICK_VOXELS];
+        assert!(source.read_brick(Vector3::new(0, 1, 0), &mut brick_buffer));
+        // Voxel (2, 9, 4) of the volume is voxel (2, 1, 4) of the brick
+        assert_eq!(brick_voxel(&brick_buffer, 2, 1, 4), &[2, 9, 4, 255]);
+        assert_eq!(brick_voxel(&brick_buffer, 0, 2, 3), &[0, 10, 3, 255]);
+        // Outside of the volume
+        assert_eq!(brick_voxel(&brick_buffer, 3, 0, 0), &[0, 0, 0, 0]);
+        assert_eq!(brick_voxel(&brick_buffer, 0, 3, 0), &[0, 0, 0, 0]);
+        assert_eq!(brick_voxel(&brick_buffer, 0, 0, 5), &[0, 0, 0, 0]);
+
+        // Brick past the end of the volume on x
+        assert!(!source.read_brick(Vector3::new(1, 0, 0), &mut brick_buffer));
+    }
+}
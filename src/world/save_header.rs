@@ -0,0 +1,124 @@
+use std::io::{Error, ErrorKind};
+
+use cgmath::{Vector2, Vector3};
+
+use super::voxel_buffer::BYTES_PER_VOXEL;
+
+// Saves starting with this tag store their dimensions on 32 bits, the older ones store
+// them on one byte and can't exceed 255 voxels per axis
+const WIDE_HEADER_TAG: &[u8; 4] = b"BOLV";
+// Voxel size (3 bytes) then dimensions (3 bytes)
+const LEGACY_HEADER_SIZE: usize = 6;
+// Tag, voxel size (3 bytes) then dimensions (3 little endian u32)
+const WIDE_HEADER_SIZE: usize = 4 + 3 + 3 * 4;
+
+// Header of a volume save, followed by the voxels with z varying fastest
+#[derive(Clone, Copy, Debug)]
+pub struct SaveHeader {
+    // Size of a voxel in world units, across the slices (x and y) and between slices (z)
+    pub voxel_size: Vector2<f32>,
+    // The same size in millimetres, as measured by the scanner
+    pub spacing: Vector2<f32>,
+    pub dimensions: Vector3<usize>,
+    // Offset of the first voxel in the file
+    pub size: usize,
+}
+
+impl SaveHeader {
+    // Read the header at the start of a save and check the file holds all its voxels
+    pub fn parse(bytes: &[u8]) -> std::io::Result<Self> {
+        let header = if bytes.starts_with(WIDE_HEADER_TAG) {
+            if bytes.len() < WIDE_HEADER_SIZE {
+                return Err(Self::too_small());
+            }
+            let dimension = |index: usize| {
+                let start = 7 + 4 * index;
+                u32::from_le_bytes(bytes[start..start + 4].try_into().unwrap()) as usize
+            };
+            Self {
+                voxel_size: Self::voxel_size(&bytes[4..7]),
+                spacing: Self::spacing(&bytes[4..7]),
+                dimensions: Vector3::new(dimension(0), dimension(1), dimension(2)),
+                size: WIDE_HEADER_SIZE,
+            }
+        } else {
+            if bytes.len() < LEGACY_HEADER_SIZE {
+                return Err(Self::too_small());
+            }
+            Self {
+                voxel_size: Self::voxel_size(&bytes[0..3]),
+                spacing: Self::spacing(&bytes[0..3]),
+                dimensions: Vector3::new(bytes[3] as usize, bytes[4] as usize, bytes[5] as usize),
+                size: LEGACY_HEADER_SIZE,
+            }
+        };
+        if bytes.len() < header.size + header.data_size() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "volume save is truncated",
+            ));
+        }
+        Ok(header)
+    }
+
+    // The voxel size is stored in tenths of a millimetre
+    fn voxel_size(bytes: &[u8]) -> Vector2<f32> {
+        Vector2::new(bytes[0] as f32 * 0.001, bytes[2] as f32 * 0.001)
+    }
+
+    fn spacing(bytes: &[u8]) -> Vector2<f32> {
+        Vector2::new(bytes[0] as f32 * 0.1, bytes[2] as f32 * 0.1)
+    }
+
+    fn too_small() -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            "file is too small to be a volume save",
+        )
+    }
+
+    // Size of the voxels following the header, in bytes
+    pub fn data_size(&self) -> usize {
+        BYTES_PER_VOXEL * self.dimensions.x * self.dimensions.y * self.dimensions.z
+    }
+
+    // Offset in the file of the voxel at `voxel`
+    pub fn voxel_offset(&self, voxel: Vector3<usize>) -> usize {
+        let index = (voxel.x * self.dimensions.y + voxel.y) * self.dimensions.z + voxel.z;
+        self.size + BYTES_PER_VOXEL * index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    #[test]
+    fn parse_legacy_and_wide_headers() {
+        let mut legacy = vec![10, 0, 5, 2, 3, 4];
+        legacy.resize(LEGACY_HEADER_SIZE + BYTES_PER_VOXEL * 2 * 3 * 4, 0);
+        let header = SaveHeader::parse(&legacy).unwrap();
+        assert_eq!(header.dimensions, Vector3::new(2, 3, 4));
+        assert_eq!(header.size, LEGACY_HEADER_SIZE);
+
+        let mut wide = b"BOLV".to_vec();
+        wide.extend_from_slice(&[10, 0, 5]);
+        for size in [600u32, 1, 2] {
+            wide.extend_from_slice(&size.to_le_bytes());
+        }
+        wide.resize(WIDE_HEADER_SIZE + BYTES_PER_VOXEL * 600 * 2, 0);
+        let header = SaveHeader::parse(&wide).unwrap();
+        assert_eq!(header.dimensions, Vector3::new(600, 1, 2));
+        assert!((header.voxel_size - Vector2::new(0.01, 0.005)).magnitude() < 1e-6);
+        assert!((header.spacing - Vector2::new(1.0, 0.5)).magnitude() < 1e-6);
+        assert_eq!(
+            header.voxel_offset(Vector3::new(1, 0, 1)),
+            WIDE_HEADER_SIZE + 12
+        );
+
+        wide.pop();
+        assert!(SaveHeader::parse(&wide).is_err());
+    }
+}
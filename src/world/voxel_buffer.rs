@@ -0,0 +1,168 @@
+use cgmath::{Vector3, Vector4};
+
+use super::world_data::Bloc;
+
+// Number of bytes used by one voxel (RGBA8)
+pub const BYTES_PER_VOXEL: usize = 4;
+
+// Cubic grid of voxels stored in a single contiguous RGBA8 buffer.
+// Layout is x-major (x varies fastest, then y, then z) so the buffer can be
+// uploaded as-is to a TEXTURE_3D.
+pub struct VoxelBuffer {
+    size: usize,
+    data: Box<[u8]>,
+}
+
+impl VoxelBuffer {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            data: vec![0u8; size * size * size * BYTES_PER_VOXEL].into_boxed_slice(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn contains(&self, pos: Vector3<i32>) -> bool {
+        pos.x >= 0
+            && pos.y >= 0
+            && pos.z >= 0
+            && (pos.x as usize) < self.size
+            && (pos.y as usize) < self.size
+            && (pos.z as usize) < self.size
+    }
+
+    // Offset of the first byte of the voxel at `pos`, or None if outside the grid
+    fn offset(&self, pos: Vector3<i32>) -> Option<usize> {
+        if !self.contains(pos) {
+            return None;
+        }
+        let index =
+            pos.x as usize + self.size * pos.y as usize + self.size * self.size * pos.z as usize;
+        Some(BYTES_PER_VOXEL * index)
+    }
+
+    pub fn get(&self, pos: Vector3<i32>) -> Option<Bloc> {
+        let offset = self.offset(pos)?;
+        let voxel = &self.data[offset..offset + BYTES_PER_VOXEL];
+        Some(Bloc {
+            color: Vector4::new(voxel[0], voxel[1], voxel[2], voxel[3]),
+        })
+    }
+
+    // Returns false (and does nothing) if `pos` is outside the grid
+    pub fn set(&mut self, pos: Vector3<i32>, bloc: Bloc) -> bool {
+        match self.offset(pos) {
+            Some(offset) => {
+                self.data[offset..offset + BYTES_PER_VOXEL].copy_from_slice(&[
+                    bloc.color.x,
+                    bloc.color.y,
+                    bloc.color.z,
+                    bloc.color.w,
+                ]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Iterate over every voxel in [min, max) (clamped to the grid), x varying fastest
+    pub fn iter_region(
+        &self,
+        min: Vector3<i32>,
+        max: Vector3<i32>,
+    ) -> impl Iterator<Item = (Vector3<i32>, Bloc)> + '_ {
+        let size = self.size as i32;
+        let min = Vector3::new(min.x.max(0), min.y.max(0), min.z.max(0));
+        let max = Vector3::new(max.x.min(size), max.y.min(size), max.z.min(size));
+        (min.z..max.z).flat_map(move |z| {
+            (min.y..max.y).flat_map(move |y| {
+                (min.x..max.x).map(move |x| {
+                    let pos = Vector3::new(x, y, z);
+                    (pos, self.get(pos).unwrap())
+                })
+            })
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Vector3<i32>, Bloc)> + '_ {
+        let size = self.size as i32;
+        self.iter_region(Vector3::new(0, 0, 0), Vector3::new(size, size, size))
+    }
+
+    // Smallest box [min, max) containing every visible voxel, None if the grid is empty
+    pub fn visible_bounds(&self) -> Option<(Vector3<usize>, Vector3<usize>)> {
+        let mut bounds: Option<(Vector3<usize>, Vector3<usize>)> = None;
+        for (pos, bloc) in self.iter() {
+            if bloc.color.w == 0 {
+                continue;
+            }
+            let pos = pos.map(|coordinate| coordinate as usize);
+            let (min, max) = bounds.get_or_insert((pos, pos + Vector3::new(1, 1, 1)));
+            *min = Vector3::new(min.x.min(pos.x), min.y.min(pos.y), min.z.min(pos.z));
+            *max = Vector3::new(
+                max.x.max(pos.x + 1),
+                max.y.max(pos.y + 1),
+                max.z.max(pos.z + 1),
+            );
+        }
+        bounds
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bloc(value: u8) -> Bloc {
+        Bloc {
+            color: Vector4::new(value, value, value, 255),
+        }
+    }
+
+    #[test]
+    fn get_returns_the_voxel_set() {
+        let mut voxels = VoxelBuffer::new(4);
+        assert!(voxels.set(Vector3::new(1, 2, 3), bloc(7)));
+        assert_eq!(
+            voxels.get(Vector3::new(1, 2, 3)).unwrap().color,
+            Vector4::new(7, 7, 7, 255)
+        );
+        assert_eq!(
+            voxels.get(Vector3::new(3, 2, 1)).unwrap().color,
+            Vector4::new(0, 0, 0, 0)
+        );
+        assert!(voxels.get(Vector3::new(4, 0, 0)).is_none());
+        assert!(voxels.get(Vector3::new(0, -1, 0)).is_none());
+        assert!(!voxels.set(Vector3::new(0, 0, 4), bloc(7)));
+    }
+
+    #[test]
+    fn iter_region_is_clamped_to_the_grid_with_x_fastest() {
+        let mut voxels = VoxelBuffer::new(3);
+        voxels.set(Vector3::new(2, 0, 1), bloc(5));
+        let region = voxels
+            .iter_region(Vector3::new(1, -2, 1), Vector3::new(5, 1, 2))
+            .map(|(pos, bloc)| (pos, bloc.color.x))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            region,
+            vec![(Vector3::new(1, 0, 1), 0), (Vector3::new(2, 0, 1), 5)]
+        );
+        assert_eq!(voxels.iter().count(), 27);
+        assert_eq!(
+            voxels.visible_bounds(),
+            Some((Vector3::new(2, 0, 1), Vector3::new(3, 1, 2)))
+        );
+    }
+}
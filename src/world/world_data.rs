@@ -1,10 +1,20 @@
-use cgmath::{Vector2, Vector3, Vector4};
+use cgmath::{ElementWise, Matrix4, Vector2, Vector3, Vector4};
 use gl::types::GLuint;
 
 use bracket_noise::prelude::*;
 use rand::Rng;
 
+use super::{
+    brick::{BrickGrid, OccupancyTexture, BRICK_SIZE, MACRO_CELL_SIZE},
+    loader::LoadedVolume,
+    lod::{LodPyramid, LOD_DISTANCE_IN_VOXELS},
+    paging::{PagedVolume, ATLAS_BRICKS_PER_AXIS},
+    voxel_buffer::{VoxelBuffer, BYTES_PER_VOXEL},
+};
+
 pub const WORLD_SIZE: usize = 512;
+// Level of detail scanned to find the visible part of the volume
+const BOUNDS_LOD_LEVEL: usize = 2;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Bloc {
@@ -12,40 +22,42 @@ pub struct Bloc {
 }
 
 pub struct WorldData {
-    blocs: Vec<Vec<Vec<Bloc>>>,
+    voxels: VoxelBuffer,
+    bricks: BrickGrid,
+    lod: LodPyramid,
     world_data_texture: WorldDataTexture,
+    occupancy_texture: OccupancyTexture,
+    paged_volume: Option<PagedVolume>,
     pub voxel_size: Vector2<f32>,
+    // Voxel size in millimetres read from the save, None for a generated volume
+    pub spacing: Option<Vector2<f32>>,
+    // Number of voxels of the volume on each axis, the world texture may be larger
+    dimensions: Vector3<usize>,
 }
 
 pub struct WorldDataTexture {
     pub texture_id: GLuint,
-    buffer: Box<[u8]>,
 }
 
 impl WorldData {
     pub unsafe fn new(voxel_size: Vector2<f32>) -> Self {
-        let blocs = vec![
-            vec![
-                vec![
-                    Bloc {
-                        color: Vector4 {
-                            x: 0,
-                            y: 0,
-                            z: 0,
-                            w: 0
-                        }
-                    };
-                    WORLD_SIZE
-                ];
-                WORLD_SIZE
-            ];
-            WORLD_SIZE
-        ];
+        let voxels = VoxelBuffer::new(WORLD_SIZE);
+        let bricks = BrickGrid::new(&voxels);
+        let lod = LodPyramid::empty(WORLD_SIZE);
+        let world_data_texture = WorldDataTexture::new(&voxels, &lod);
+        let occupancy_texture = OccupancyTexture::new();
+        occupancy_texture.regenerate_texture(&bricks);
 
         Self {
-            blocs: blocs,
-            world_data_texture: WorldDataTexture::new(),
+            voxels,
+            bricks,
+            lod,
+            world_data_texture,
+            occupancy_texture,
+            paged_volume: None,
             voxel_size,
+            spacing: None,
+            dimensions: Vector3::new(WORLD_SIZE, WORLD_SIZE, WORLD_SIZE),
         }
     }
 
@@ -58,7 +70,7 @@ impl WorldData {
                         .fract();
 
                     if random < -0.970 {
-                        self.change_bloc_without_regen(
+                        self.voxels.set(
                             Vector3 {
                                 x: x as i32,
                                 y: y as i32,
@@ -77,7 +89,9 @@ impl WorldData {
                 }
             }
         }
-        self.regenerate_texture();
+        self.bricks.rebuild(&self.voxels);
+        self.lod = LodPyramid::new(&self.voxels);
+        self.upload_textures();
     }
 
     pub fn generate_bottle(&mut self) {
@@ -90,7 +104,7 @@ impl WorldData {
                                 + (z as i32 - WORLD_SIZE as i32 / 2).pow(2))
                                 < (WORLD_SIZE as i32 / 3).pow(2))
                         {
-                            self.change_bloc_without_regen(
+                            self.voxels.set(
                                 Vector3 {
                                     x: x as i32,
                                     y: y as i32,
@@ -110,42 +124,97 @@ impl WorldData {
                 }
             }
         }
-        self.regenerate_texture();
+        self.bricks.rebuild(&self.voxels);
+        self.lod = LodPyramid::new(&self.voxels);
+        self.upload_textures();
     }
 
-    #[allow(unused)]
-    pub fn load_world_from_file(&mut self, save_name: &str) {
-        let flatten = std::fs::read(format!("saves/{}.bin", save_name)).unwrap();
-        self.voxel_size = Vector2::new(flatten[0] as f32 * 0.001, flatten[2] as f32 * 0.001);
-        let world_size = Vector3::new(
-            flatten[3] as usize,
-            flatten[4] as usize,
-            flatten[5] as usize,
-        );
-        let mut i = 0;
-        for x in 0..world_size.x {
-            for y in 0..world_size.y {
-                for z in 0..world_size.z {
-                    self.change_bloc_without_regen(
-                        Vector3 {
-                            x: x as i32,
-                            y: y as i32,
-                            z: z as i32,
-                        },
-                        Bloc {
-                            color: Vector4::new(
-                                flatten[4 * i + 0 + 6],
-                                flatten[4 * i + 1 + 6],
-                                flatten[4 * i + 2 + 6],
-                                flatten[4 * i + 3 + 6],
-                            ),
-                        },
-                    );
-                    i += 1;
-                }
+    // Replace the volume by one decoded by the loader thread and upload it
+    pub fn set_loaded_volume(&mut self, loaded_volume: LoadedVolume) {
+        match loaded_volume {
+            LoadedVolume::InMemory {
+                voxel_size,
+                spacing,
+                dimensions,
+                voxels,
+                bricks,
+                lod,
+            } => {
+                self.voxel_size = voxel_size;
+                self.spacing = Some(spacing);
+                self.dimensions = dimensions;
+                self.voxels = voxels;
+                self.bricks = bricks;
+                self.lod = lod;
+                self.paged_volume = None;
             }
+            // Keep the save on disk and only upload the bricks seen by the camera.
+            // The in-memory grid is released since voxels are read from the mapped file.
+            LoadedVolume::Streamed { source, lod } => {
+                self.voxel_size = source.header.voxel_size;
+                self.spacing = Some(source.header.spacing);
+                self.dimensions = source.header.dimensions;
+                self.voxels = VoxelBuffer::new(0);
+                self.bricks = BrickGrid::new(&self.voxels);
+                self.lod = lod;
+                self.paged_volume = Some(unsafe { PagedVolume::new(source) });
+            }
+        }
+        self.upload_textures();
+    }
+
+    // Stream the bricks inside the view frustum of `mvp` closer than `max_distance`
+    pub unsafe fn update_streaming(
+        &mut self,
+        camera_position: Vector3<f32>,
+        mvp: Matrix4<f32>,
+        max_distance: f32,
+    ) {
+        if let Some(paged_volume) = self.paged_volume.as_mut() {
+            paged_volume.update(camera_position, mvp, max_distance);
+        }
+    }
+
+    // Voxels along each side of the cubic grid the textures are sampled in, larger than
+    // the volume when it is not cubic
+    pub fn grid_size(&self) -> usize {
+        match self.paged_volume.as_ref() {
+            Some(paged_volume) => paged_volume.bricks_per_axis() * BRICK_SIZE,
+            None => self.voxels.size(),
         }
-        self.regenerate_texture();
+    }
+
+    // Size of the volume in world units
+    pub fn extent(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.dimensions.x as f32 * self.voxel_size.x,
+            self.dimensions.y as f32 * self.voxel_size.x,
+            self.dimensions.z as f32 * self.voxel_size.y,
+        )
+    }
+
+    // Bounding box in world units of the visible voxels, or of the whole volume if it is
+    // empty. Computed on a coarse level of detail, so it may be slightly larger.
+    pub fn content_bounds(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let bounds = self
+            .lod
+            .levels()
+            .find(|(level, _)| *level >= BOUNDS_LOD_LEVEL)
+            .and_then(|(level, voxels)| {
+                let (minimum, maximum) = voxels.visible_bounds()?;
+                let cell_size =
+                    Vector3::new(self.voxel_size.x, self.voxel_size.x, self.voxel_size.y)
+                        * (1 << level) as f32;
+                Some((
+                    minimum.cast::<f32>()?.mul_element_wise(cell_size),
+                    maximum.cast::<f32>()?.mul_element_wise(cell_size),
+                ))
+            });
+        bounds.unwrap_or((Vector3::new(0.0, 0.0, 0.0), self.extent()))
+    }
+
+    pub fn is_streamed(&self) -> bool {
+        self.paged_volume.is_some()
     }
 
     fn is_board(&self, pos: Vector3<i32>) -> bool {
@@ -179,7 +248,7 @@ impl WorldData {
 
                 for y in 0..height {
                     if !self.is_board(Vector3::new(x as i32, y as i32, z as i32)) {
-                        self.change_bloc_without_regen(
+                        self.voxels.set(
                             Vector3 {
                                 x: x as i32,
                                 y: y as i32,
@@ -199,41 +268,98 @@ impl WorldData {
             }
         }
         println!("Ground generated");
-        self.regenerate_texture();
+        self.bricks.rebuild(&self.voxels);
+        self.lod = LodPyramid::new(&self.voxels);
+        self.upload_textures();
     }
 
-    fn change_bloc_without_regen(&mut self, pos: Vector3<i32>, bloc: Bloc) {
-        if Self::is_outside_world(pos) {
-            return;
+    fn upload_textures(&self) {
+        unsafe {
+            self.world_data_texture
+                .regenerate_texture(&self.voxels, &self.lod);
+            self.occupancy_texture.regenerate_texture(&self.bricks);
         }
-        self.blocs[pos.x as usize][pos.y as usize][pos.z as usize] = bloc;
-        self.world_data_texture.change_id(pos, bloc, false);
     }
 
-    pub fn regenerate_texture(&self) {
-        unsafe {
-            self.world_data_texture.regenerate_texture();
+    pub fn texture_id(&self) -> GLuint {
+        self.world_data_texture.texture_id
+    }
+
+    pub fn occupancy_texture_id(&self) -> GLuint {
+        self.occupancy_texture.texture_id
+    }
+
+    pub fn macro_occupancy_texture_id(&self) -> GLuint {
+        self.occupancy_texture.macro_texture_id
+    }
+
+    pub fn bricks_per_axis(&self) -> usize {
+        match self.paged_volume.as_ref() {
+            Some(paged_volume) => paged_volume.bricks_per_axis(),
+            None => self.bricks.bricks_per_axis(),
+        }
+    }
+
+    // Distance from the camera under which the full resolution is used
+    pub fn lod_distance(&self) -> f32 {
+        LOD_DISTANCE_IN_VOXELS * self.voxel_size.x
+    }
+
+    pub fn max_lod_level(&self) -> usize {
+        self.lod.last_level()
+    }
+
+    // Finest level held by the world texture: 0 unless the volume is streamed
+    pub fn preview_lod_level(&self) -> usize {
+        if self.voxels.size() > 0 {
+            0
+        } else {
+            self.lod.first_level()
         }
     }
 
-    pub unsafe fn bind_texture(&self) {
-        self.world_data_texture.bind_texture();
+    pub fn atlas_bricks_per_axis(&self) -> usize {
+        ATLAS_BRICKS_PER_AXIS
+    }
+
+    // Textures of the streamed volume, or no texture if the volume is fully loaded
+    pub fn atlas_texture_id(&self) -> GLuint {
+        self.paged_volume
+            .as_ref()
+            .map_or(0, |paged_volume| paged_volume.atlas_texture_id())
+    }
+
+    pub fn indirection_texture_id(&self) -> GLuint {
+        self.paged_volume
+            .as_ref()
+            .map_or(0, |paged_volume| paged_volume.indirection_texture_id())
     }
 
-    pub fn is_outside_world(position: Vector3<i32>) -> bool {
-        return position.x < 0
-            || position.y < 0
-            || position.z < 0
-            || position.x >= WORLD_SIZE as i32
-            || position.y >= WORLD_SIZE as i32
-            || position.z >= WORLD_SIZE as i32;
+    // Memory used by the volume textures on the GPU, in bytes
+    pub fn texture_memory(&self) -> usize {
+        let level_bytes = |size: usize| size * size * size * BYTES_PER_VOXEL;
+        let world_data_bytes = level_bytes(self.voxels.size())
+            + self
+                .lod
+                .levels()
+                .map(|(_, level_voxels)| level_bytes(level_voxels.size()))
+                .sum::<usize>();
+        // Two bytes per brick and per macro cell for the intensity ranges
+        let bricks_per_axis = self.bricks_per_axis();
+        let macro_cells_per_axis = bricks_per_axis.div_ceil(MACRO_CELL_SIZE);
+        let occupancy_bytes = 2 * (bricks_per_axis.pow(3) + macro_cells_per_axis.pow(3));
+        let paging_bytes = if self.paged_volume.is_some() {
+            level_bytes(ATLAS_BRICKS_PER_AXIS * BRICK_SIZE) + level_bytes(bricks_per_axis)
+        } else {
+            0
+        };
+        world_data_bytes + occupancy_bytes + paging_bytes
     }
 }
 
 impl WorldDataTexture {
-    pub unsafe fn new() -> Self {
+    pub unsafe fn new(voxels: &VoxelBuffer, lod: &LodPyramid) -> Self {
         let mut texture_id: GLuint = 0;
-        let buffer = vec![0u8; WORLD_SIZE * WORLD_SIZE * WORLD_SIZE * 4].into_boxed_slice();
         unsafe {
             gl::GenTextures(1, &mut texture_id);
             gl::BindTexture(gl::TEXTURE_3D, texture_id);
@@ -252,73 +378,56 @@ impl WorldDataTexture {
                 gl::TEXTURE_WRAP_R,
                 gl::CLAMP_TO_BORDER as i32,
             );
-            gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-            gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-            gl::TexImage3D(
+            gl::TexParameteri(
                 gl::TEXTURE_3D,
-                0,
-                gl::RGBA8 as i32,
-                WORLD_SIZE as i32,
-                WORLD_SIZE as i32,
-                WORLD_SIZE as i32,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                buffer.as_ptr() as *const std::ffi::c_void,
+                gl::TEXTURE_MIN_FILTER,
+                gl::NEAREST_MIPMAP_NEAREST as i32,
             );
+            gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
         }
 
-        Self {
-            texture_id: texture_id,
-            buffer: buffer,
-        }
+        let world_data_texture = Self { texture_id };
+        world_data_texture.regenerate_texture(voxels, lod);
+        world_data_texture
     }
 
-    pub unsafe fn regenerate_texture(&self) {
+    // Full resolution volume is stored in mipmap level 0 and the pyramid in the next levels
+    pub unsafe fn regenerate_texture(&self, voxels: &VoxelBuffer, lod: &LodPyramid) {
         gl::BindTexture(gl::TEXTURE_3D, self.texture_id);
+        Self::upload_level(0, voxels);
+        // Release levels which are not computed (streamed volume)
+        for level in 1..lod.first_level() {
+            Self::upload_level(level, &VoxelBuffer::new(0));
+        }
+        for (level, level_voxels) in lod.levels() {
+            Self::upload_level(level, level_voxels);
+        }
+
+        let base_level = if voxels.size() > 0 {
+            0
+        } else {
+            lod.first_level()
+        };
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_BASE_LEVEL, base_level as i32);
+        gl::TexParameteri(
+            gl::TEXTURE_3D,
+            gl::TEXTURE_MAX_LEVEL,
+            lod.last_level() as i32,
+        );
+    }
+
+    unsafe fn upload_level(level: usize, voxels: &VoxelBuffer) {
         gl::TexImage3D(
             gl::TEXTURE_3D,
-            0,
+            level as i32,
             gl::RGBA8 as i32,
-            WORLD_SIZE as i32,
-            WORLD_SIZE as i32,
-            WORLD_SIZE as i32,
+            voxels.size() as i32,
+            voxels.size() as i32,
+            voxels.size() as i32,
             0,
             gl::RGBA,
             gl::UNSIGNED_BYTE,
-            self.buffer.as_ptr() as *const std::ffi::c_void,
+            voxels.as_bytes().as_ptr() as *const std::ffi::c_void,
         );
     }
-
-    pub fn change_id(&mut self, pos: Vector3<i32>, bloc: Bloc, do_regenerate: bool) {
-        self.buffer[4
-            * (pos.x as usize
-                + WORLD_SIZE * pos.y as usize
-                + WORLD_SIZE * WORLD_SIZE * pos.z as usize)
-            + 0] = bloc.color.x;
-        self.buffer[4
-            * (pos.x as usize
-                + WORLD_SIZE * pos.y as usize
-                + WORLD_SIZE * WORLD_SIZE * pos.z as usize)
-            + 1] = bloc.color.y;
-        self.buffer[4
-            * (pos.x as usize
-                + WORLD_SIZE * pos.y as usize
-                + WORLD_SIZE * WORLD_SIZE * pos.z as usize)
-            + 2] = bloc.color.z;
-        self.buffer[4
-            * (pos.x as usize
-                + WORLD_SIZE * pos.y as usize
-                + WORLD_SIZE * WORLD_SIZE * pos.z as usize)
-            + 3] = bloc.color.w;
-        if do_regenerate {
-            unsafe {
-                self.regenerate_texture();
-            }
-        }
-    }
-
-    pub unsafe fn bind_texture(&self) {
-        gl::BindTexture(gl::TEXTURE_3D, self.texture_id);
-    }
 }